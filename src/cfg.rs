@@ -1,4 +1,9 @@
-use crate::Flag::{self, *};
+use std::num::NonZeroUsize;
+
+use crate::{
+    Mode,
+    Flag::{self, *}
+};
 
 #[derive(Default)]
 pub struct RConfig {
@@ -23,7 +28,41 @@ macro_rules! setter {
     }
 }
 
+// Same as the `setter`, but for flags without arguments
+macro_rules! switch {
+    ($fn: tt, $is_fn: tt, $name: tt) => {
+        pub fn $fn(&mut self) -> &mut Self {
+            self.flags.push($name);
+            self
+        }
+
+        pub fn $is_fn(&self) -> bool {
+            self.flags.iter().any(|e| matches!(e, $name))
+        }
+    }
+}
+
 impl RConfig {
     setter!{cd, if_cd, Cd, path: String}
+    setter!{jobs, if_jobs, Jobs, n: NonZeroUsize}
+    setter!{file, if_file, File, path: String}
+    switch!{dry_run, is_dry_run, DryRun}
+    switch!{always_make, is_always_make, AlwaysMake}
+    switch!{question, is_question, Question}
+    switch!{touch, is_touch, Touch}
+    switch!{hash, is_hash, Hash}
+
+    // `-q` takes precedence over `-n`, which takes precedence over `-t`
+    pub fn mode(&self) -> Mode {
+        if self.is_question() {
+            Mode::Question
+        } else if self.is_dry_run() {
+            Mode::DryRun
+        } else if self.is_touch() {
+            Mode::Touch
+        } else {
+            Mode::Execute
+        }
+    }
     // ...
 }
@@ -0,0 +1,89 @@
+use crate::Info;
+
+// Conditional directives, like in Makefile
+#[derive(Debug, Clone, Copy)]
+pub enum Directive<'a> {
+    Ifeq(&'a str),
+    Ifneq(&'a str),
+    Ifdef(&'a str),
+    Ifndef(&'a str),
+
+    /// Rest of the line, to support `else ifeq ...` chains
+    Else(&'a str),
+
+    Endif
+}
+
+impl<'a> Directive<'a> {
+    pub fn parse(line: &'a str) -> Option::<Self> {
+        use Directive::*;
+
+        let line = line.trim();
+        let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        match keyword {
+            "ifeq"   => Some(Ifeq(rest)),
+            "ifneq"  => Some(Ifneq(rest)),
+            "ifdef"  => Some(Ifdef(rest)),
+            "ifndef" => Some(Ifndef(rest)),
+            "else"   => Some(Else(rest)),
+            "endif"  => Some(Endif),
+            _        => None
+        }
+    }
+
+    pub fn keyword(&self) -> &'static str {
+        use Directive::*;
+        match self {
+            Ifeq(..)   => "ifeq",
+            Ifneq(..)  => "ifneq",
+            Ifdef(..)  => "ifdef",
+            Ifndef(..) => "ifndef",
+            Else(..)   => "else",
+            Endif      => "endif"
+        }
+    }
+
+    // Splits arguments of `ifeq`/`ifneq`, which can be written as:
+    // `(a,b)`, `"a" "b"` or `'a' 'b'`. Commas inside of `$(...)` are not separators.
+    pub fn split_args(args: &str) -> Option::<(&str, &str)> {
+        if let Some(inner) = args.strip_prefix('(').and_then(|args| args.strip_suffix(')')) {
+            let mut depth = 0usize;
+            for (idx, c) in inner.char_indices() {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth = depth.checked_sub(1)?,
+                    ',' if depth == 0 => return Some((inner[..idx].trim(), inner[idx + 1..].trim())),
+                    _ => {}
+                }
+            }
+            return None
+        }
+
+        let (lhs, rest) = Self::unquote(args)?;
+        let (rhs, rest) = Self::unquote(rest)?;
+        rest.is_empty().then_some((lhs, rhs))
+    }
+
+    // Returns the quoted string and the rest of the input
+    fn unquote(s: &str) -> Option::<(&str, &str)> {
+        let quote = s.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let end = s[1..].find(quote)? + 1;
+        Some((&s[1..end], s[end + 1..].trim_start()))
+    }
+}
+
+pub struct Cond {
+    // Whether the current branch is taken
+    pub active: bool,
+
+    // Whether one of the branches has been taken already,
+    // so the following `else` branches are skipped.
+    pub taken: bool,
+
+    // Whether the plain `else` has been seen already
+    pub in_else: bool,
+
+    // Where the conditional was opened
+    pub info: Info
+}
@@ -1,14 +1,14 @@
-use std::{
-    env,
-    collections::HashSet
-};
+use std::{env, process::exit};
 use robuild::*;
 
 use crate::{
+    Arg,
     Config,
     RConfig,
     RResult,
-    ALL_FLAGS_STR,
+    RakeError,
+    AssignOp,
+    flag::{help, version},
     error::UnwrapOrReport
 };
 
@@ -16,9 +16,10 @@ use crate::{
 pub struct Comptime {
     cfg: Config,
 
-    #[allow(unused)]
     rcfg: RConfig,
-    entered_dir: Option::<String>,
+
+    // Directories entered with `-C` and the one the Rakefile was found in, in order
+    entered_dirs: Vec::<String>,
 
     // When parsing flags, you can come across a string,
     // that is not a defined flag, and it may be a potential job,
@@ -26,7 +27,11 @@ pub struct Comptime {
     // is a defined job. Why potential? Because we are parsing flags before
     // parsing `Rakefile` whether it exists or not, and after we parsed it,
     // we can check, if the potential job is actually a defined one.
-    potential_jobs: HashSet::<String>,
+    // Kept in order they were passed in, so goals are built in that order.
+    potential_jobs: Vec::<String>,
+
+    // Variables passed like: `rake cc=clang`
+    overrides: Vec::<(String, String)>,
 }
 
 macro_rules! getter {
@@ -40,61 +45,75 @@ macro_rules! getter {
 impl Comptime {
     pub fn new() -> RResult::<Comptime> {
         use crate::Flag::{self, *};
-        use crate::RakeError::*;
-
-        let mut iter = env::args().skip(1).into_iter().peekable();
 
         let mut cfg = Config::default();
         let mut rcfg = RConfig::default();
-        let mut potential_jobs = HashSet::new();
+        let mut potential_jobs = Vec::new();
+        let mut overrides = Vec::new();
 
-        let mut skip = false;
-        while let Some(f) = iter.next() {
-            if skip { continue }
-
-            let arg = if let Some(arg) = iter.peek() {
-                if ALL_FLAGS_STR.contains(&arg.as_str()) { None }
-                else {
-                    skip = true;
-                    Some(arg.to_owned())
-                }
-            } else { None };
-
-            let farg = (f.to_owned(), arg);
-            match Flag::try_from(farg) {
-                Ok(flag) => match flag {
-                    Keepgoing => { cfg.keepgoing(true); }
-                    Silent    => { cfg.echo(false); }
-                    Cd(arg)   => { rcfg.cd(arg); }
+        for arg in Flag::parse_args(env::args().skip(1))? {
+            let f = match arg {
+                Arg::Flag(flag) => {
+                    match flag {
+                        Keepgoing  => { cfg.keepgoing(true); }
+                        Silent     => { cfg.echo(false); }
+                        Cd(dir)    => { rcfg.cd(dir); }
+                        Jobs(n)    => { rcfg.jobs(n); }
+                        File(f)    => { rcfg.file(f); }
+                        DryRun     => { rcfg.dry_run(); }
+                        AlwaysMake => { rcfg.always_make(); }
+                        Question   => { rcfg.question(); RakeError::set_exit_code(2); }
+                        Touch      => { rcfg.touch(); }
+                        Hash       => { rcfg.hash(); }
+                        Help       => { print!("{help}", help = help()); exit(0) }
+                        Version    => { println!("{version}", version = version()); exit(0) }
+                    }
+                    continue
                 }
-                Err(err) => match err {
-                    InvalidUseOfFlag(..) => return Err(err),
-                    _ => { potential_jobs.insert(f.to_owned()); }
+                Arg::Other(f) => f
+            };
+
+            if let Some((_, start, end)) = AssignOp::find(&f) {
+                let name = f[..start].trim();
+                if !name.is_empty() && !name.contains(char::is_whitespace) {
+                    overrides.push((name.to_owned(), f[end..].trim().to_owned()));
+                    continue
                 }
             }
-        }
 
-        let entered_dir = if let Some(dir) = rcfg.if_cd() {
-            log!(INFO, "Entering directory `{dir}`");
-            env::set_current_dir(&dir).unwrap_or_report();
-            Some(dir)
-        } else { None };
+            if !potential_jobs.contains(&f) { potential_jobs.push(f); }
+        }
 
-        Ok(Comptime {
+        let mut ct = Comptime {
             cfg,
             rcfg,
-            entered_dir,
-            potential_jobs
-        })
+            potential_jobs,
+            overrides,
+            ..Comptime::default()
+        };
+
+        if let Some(dir) = ct.rcfg.if_cd() {
+            ct.enter_dir(dir);
+        }
+
+        Ok(ct)
+    }
+
+    pub fn enter_dir(&mut self, dir: String) {
+        log!(INFO, "Entering directory `{dir}`");
+        env::set_current_dir(&dir).unwrap_or_report();
+        self.entered_dirs.push(dir);
     }
 
     #[inline(always)]
     pub fn handle_ucd(&self) {
-        if let Some(ref dir) = self.entered_dir {
+        for dir in self.entered_dirs.iter().rev() {
             log!(INFO, "Leaving directory `{dir}`");
         }
     }
 
     getter!{cfg: Config}
-    getter!{potential_jobs: HashSet::<String>}
+    getter!{rcfg: RConfig}
+    getter!{potential_jobs: Vec::<String>}
+    getter!{overrides: Vec::<(String, String)>}
 }
@@ -0,0 +1,152 @@
+use std::{
+    fs,
+    io,
+    path::{Path, PathBuf},
+    collections::HashMap
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    /// Hash of the commands the target was built with
+    pub cmd: u64,
+
+    /// Content hashes of the inputs and of the output, recorded only in the hash mode
+    pub content: Option::<(u64, u64)>,
+
+    /// Deps discovered from the depfile of the job
+    pub deps: Vec::<String>
+}
+
+// Persistent log of the commands the targets were built with, like ninja's `.ninja_log`.
+// Target is out of date, if the commands of its job have changed since it was built.
+// Each entry is: `<cmd hash> <inputs hash> <output hash> <target>` in hex, content hashes
+// are `-` if the target is not built in the hash mode, followed by its discovered deps,
+// one per line, indented with a tab.
+pub struct Db {
+    path: PathBuf,
+    entries: HashMap::<String, Entry>,
+
+    // Whether there is something to save
+    dirty: bool
+}
+
+impl Db {
+    pub const FILE_NAME: &'static str = ".rake_db";
+
+    const HEADER: &'static str = "# rake_db v3";
+
+    const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+    // Missing, malformed or outdated database is treated as an empty one
+    pub fn load(dir: &Path) -> Self {
+        let path = dir.join(Self::FILE_NAME);
+        let file_str = fs::read_to_string(&path).unwrap_or_default();
+        let mut lines = file_str.lines();
+        let mut entries = HashMap::new();
+        if lines.next() == Some(Self::HEADER) {
+            // Target of the last entry, that the following discovered deps belong to
+            let mut last = None::<String>;
+            for line in lines {
+                if let Some(dep) = line.strip_prefix('\t') {
+                    if let Some(entry) = last.as_ref().and_then(|target| entries.get_mut(target)) {
+                        let entry: &mut Entry = entry;
+                        entry.deps.push(dep.to_owned());
+                    }
+                    continue
+                }
+
+                last = Self::parse_entry(line).map(|(target, entry)| {
+                    entries.insert(target.clone(), entry);
+                    target
+                });
+            }
+        }
+
+        Self { path, entries, dirty: false }
+    }
+
+    fn parse_entry(line: &str) -> Option::<(String, Entry)> {
+        let mut fields = line.splitn(4, ' ');
+        let mut next = || fields.next();
+        let (cmd, inputs, output, target) = (next()?, next()?, next()?, next()?);
+
+        let cmd = u64::from_str_radix(cmd, 16).ok()?;
+        let content = match (inputs, output) {
+            ("-", "-") => None,
+            _ => Some((u64::from_str_radix(inputs, 16).ok()?, u64::from_str_radix(output, 16).ok()?))
+        };
+
+        Some((target.to_owned(), Entry { cmd, content, deps: Vec::new() }))
+    }
+
+    // FNV-1a, so that hashes stay the same across Rust versions
+    fn fnv(mut hash: u64, bytes: &[u8]) -> u64 {
+        for byte in bytes {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        hash
+    }
+
+    pub fn hash<'a>(lines: impl Iterator::<Item = &'a str>) -> u64 {
+        lines.fold(Self::FNV_OFFSET, |hash, line| Self::fnv(Self::fnv(hash, line.as_bytes()), b"\n"))
+    }
+
+    // Content hash of the file, `None` if it can't be read, e.g. it's a directory
+    pub fn hash_file(path: &str) -> Option::<u64> {
+        fs::read(path).ok().map(|bytes| Self::fnv(Self::FNV_OFFSET, &bytes))
+    }
+
+    // Hash of the names and the contents of the inputs, unreadable ones are hashed by name only
+    pub fn hash_inputs<'a>(inputs: impl Iterator::<Item = &'a str>) -> u64 {
+        inputs.fold(Self::FNV_OFFSET, |hash, input| {
+            let hash = Self::fnv(hash, input.as_bytes());
+            match Self::hash_file(input) {
+                Some(content) => Self::fnv(hash, &content.to_le_bytes()),
+                None => Self::fnv(hash, b"\0")
+            }
+        })
+    }
+
+    #[inline]
+    pub fn get(&self, target: &str) -> Option::<&Entry> {
+        self.entries.get(target)
+    }
+
+    // Targets that are not in the database yet are not considered changed
+    #[inline]
+    pub fn changed(&self, target: &str, cmd: u64) -> bool {
+        matches!(self.entries.get(target), Some(entry) if entry.cmd != cmd)
+    }
+
+    pub fn record(&mut self, target: &str, entry: Entry) {
+        if self.entries.get(target) != Some(&entry) {
+            self.entries.insert(target.to_owned(), entry);
+            self.dirty = true;
+        }
+    }
+
+    // Writes to a temporary file first, so that the database is never left half-written
+    pub fn save(&self) -> io::Result::<()> {
+        if !self.dirty { return Ok(()) }
+
+        let mut entries = self.entries.iter().collect::<Vec::<_>>();
+        entries.sort_unstable_by_key(|(target, _)| *target);
+
+        let mut out = format!("{header}\n", header = Self::HEADER);
+        for (target, Entry { cmd, content, deps }) in entries {
+            let (inputs, output) = match content {
+                Some((inputs, output)) => (format!("{inputs:016x}"), format!("{output:016x}")),
+                None => ("-".to_owned(), "-".to_owned())
+            };
+            out.push_str(&format!("{cmd:016x} {inputs} {output} {target}\n"));
+            for dep in deps {
+                out.push_str(&format!("\t{dep}\n"));
+            }
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, out)?;
+        fs::rename(&tmp, &self.path)
+    }
+}
@@ -0,0 +1,110 @@
+use crate::Info;
+
+// Declaration of the depfile, like: `build/%.o: depfile = $t.d`, or the one implied by `.DEPFILE`
+#[derive(Debug, Clone)]
+pub struct Depfile {
+    /// Path with special symbols unexpanded, like: `$t.d`
+    pub path: String,
+
+    /// Where the depfile is declared, along with the source line to point errors at
+    pub info: Info,
+    pub src: String
+}
+
+// Parses make-format depfiles, like the ones `gcc -MD` writes:
+// ```
+// build/foo.o: src/foo.c src/bar.h \
+//   src/baz.h
+// src/bar.h:
+// ```
+// Returns the deps of all of the rules, deduplicated, in order.
+pub fn parse_depfile(file_str: &str) -> Vec::<String> {
+    let joined = file_str.replace("\\\r\n", " ").replace("\\\n", " ");
+
+    let mut ret = Vec::<String>::new();
+    for line in joined.lines() {
+        let Some(deps) = rule_deps(line) else { continue };
+        for dep in split_paths(deps) {
+            if !ret.contains(&dep) { ret.push(dep) }
+        }
+    }
+    ret
+}
+
+// Part of the rule after the `:`, that separates targets from deps.
+// The `:` must be followed by whitespace, so that paths like `C:\foo` are not split.
+fn rule_deps(line: &str) -> Option::<&str> {
+    let bytes = line.as_bytes();
+    (0..bytes.len())
+        .find(|idx| bytes[*idx] == b':' && bytes.get(idx + 1).is_none_or(u8::is_ascii_whitespace))
+        .map(|idx| &line[idx + 1..])
+}
+
+// Splits paths by whitespace, handling escapes: `\ `, `\#` and `$$`
+fn split_paths(s: &str) -> Vec::<String> {
+    let mut ret = Vec::new();
+    let mut path = String::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if matches!(chars.peek(), Some(' ' | '#')) => path.push(chars.next().unwrap()),
+            '$' if chars.peek() == Some(&'$') => { chars.next(); path.push('$') }
+            c if c.is_whitespace() => if !path.is_empty() { ret.push(std::mem::take(&mut path)) }
+            c => path.push(c)
+        }
+    }
+
+    if !path.is_empty() { ret.push(path) }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_rule() {
+        assert_eq!(parse_depfile("build/foo.o: src/foo.c src/bar.h\n"), ["src/foo.c", "src/bar.h"]);
+    }
+
+    #[test]
+    fn continuations() {
+        let depfile = "build/foo.o: src/foo.c \\\n  src/bar.h \\\r\n  src/baz.h\n";
+        assert_eq!(parse_depfile(depfile), ["src/foo.c", "src/bar.h", "src/baz.h"]);
+    }
+
+    #[test]
+    fn phony_rules_of_headers() {
+        // Written by `gcc -MP`
+        let depfile = "build/foo.o: src/foo.c src/bar.h\n\nsrc/bar.h:\n";
+        assert_eq!(parse_depfile(depfile), ["src/foo.c", "src/bar.h"]);
+    }
+
+    #[test]
+    fn deduplicates_in_order() {
+        let depfile = "a.o: a.c x.h\nb.o: b.c x.h a.c\n";
+        assert_eq!(parse_depfile(depfile), ["a.c", "x.h", "b.c"]);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(parse_depfile("a.o: my\\ file.c cost$$.h \\#hash.h\n"), ["my file.c", "cost$.h", "#hash.h"]);
+    }
+
+    #[test]
+    fn other_backslashes_are_kept() {
+        assert_eq!(parse_depfile("a.o: src\\a.c\n"), ["src\\a.c"]);
+    }
+
+    #[test]
+    fn drive_letters() {
+        assert_eq!(parse_depfile("C:\\build\\a.o: C:\\src\\a.c D:/b.h\n"), ["C:\\src\\a.c", "D:/b.h"]);
+    }
+
+    #[test]
+    fn empty_and_malformed() {
+        assert!(parse_depfile("").is_empty());
+        assert!(parse_depfile("no rules here\n").is_empty());
+        assert!(parse_depfile("a.o:\n").is_empty());
+    }
+}
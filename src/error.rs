@@ -1,23 +1,75 @@
 use std::{
     process::exit,
+    sync::atomic::{AtomicI32, Ordering},
     fmt::{Display, Formatter},
 };
 use robuild::*;
-use crate::Rakefile;
+use crate::{Func, Rakefile};
 
 const EXPECTED_TAB_WIDTH: usize = Rakefile::TAB_WIDTH;
 
-// File path, row
+// `-q` exits with 1 if the goals are not up to date, so errors exit with 2 there, like in make
+static EXIT_CODE: AtomicI32 = AtomicI32::new(1);
+
+// Offending part of the line: `line[start..end]`
+#[derive(Debug, Clone)]
+pub struct Span {
+    pub line: String,
+    pub start: usize,
+    pub end: usize
+}
+
+impl Span {
+    // Width of the text as it's printed, tabs are expanded to the `EXPECTED_TAB_WIDTH`
+    fn width(s: &str) -> usize {
+        s.chars().map(|c| if c == '\t' { EXPECTED_TAB_WIDTH } else { 1 }).sum()
+    }
+
+    // Renders the line with carets under the span, like:
+    // ```
+    //   |
+    // 3 | foo: $(undefined)
+    //   |      ^^^^^^^^^^^^
+    // ```
+    fn render(&self, f: &mut Formatter, row: usize) -> std::fmt::Result {
+        let pad = " ".repeat(row.to_string().len());
+        let line = self.line.trim_end().replace('\t', &" ".repeat(EXPECTED_TAB_WIDTH));
+        let offset = Self::width(&self.line[..self.start]);
+        let carets = Self::width(&self.line[self.start..self.end]).max(1);
+        write!(f, "\n{pad} |\n{row} | {line}\n{pad} | {spaces}{carets}", spaces = " ".repeat(offset), carets = "^".repeat(carets))
+    }
+}
+
+// File path, row, span
 #[derive(Debug, Clone)]
-pub struct Info(pub String, pub usize);
+pub struct Info(pub String, pub usize, pub Option::<Span>);
+
+impl Info {
+    pub fn span(mut self, line: &str, start: usize, end: usize) -> Self {
+        self.2 = Some(Span { line: line.to_owned(), start, end });
+        self
+    }
+
+    // Spans the first occurrence of the token in the line,
+    // or the whole line if the token is not there, e.g. it came from expansion of a variable.
+    pub fn at(self, line: &str, token: &str) -> Self {
+        match line.find(token).filter(|_| !token.is_empty()) {
+            Some(start) => self.span(line, start, start + token.len()),
+            None => {
+                let start = line.len() - line.trim_start().len();
+                self.span(line, start, line.trim_end().len().max(start))
+            }
+        }
+    }
+}
 
-impl From::<&Rakefile<'_>> for Info {
+impl From::<&Rakefile> for Info {
     #[inline]
     fn from(rake: &Rakefile) -> Self {
-        Self(rake.file_path.display().to_string(), rake.row)
+        Self(rake.file_path.display().to_string(), rake.row, None)
     }
 }
-impl From::<(&Rakefile<'_>, usize)> for Info {
+impl From::<(&Rakefile, usize)> for Info {
     #[inline]
     fn from(rrow: (&Rakefile, usize)) -> Self {
         let mut info = Self::from(rrow.0);
@@ -37,6 +89,23 @@ pub enum RakeError {
     /// Directory path
     NoRakefileInDir(String),
 
+    /// Path of the Rakefile and the error
+    FailedToRead(String, String),
+
+    /// Directive, like: `endif` or `else`
+    UnmatchedDirective(Info, String),
+
+    /// Opened conditional, missing its `endif`
+    UnterminatedConditional(Info),
+
+    InvalidConditional(Info, String),
+
+    /// Path of the file passed to `include`
+    IncludeNotFound(Info, String),
+
+    /// Path of the file that includes itself (eventually)
+    RecursiveInclude(Info, String),
+
     /// Can be happen in case of $d[index] syntax.
     DepsIndexOutOfBounds(Info, usize),
 
@@ -54,38 +123,181 @@ pub enum RakeError {
 
     MultipleNames(Info),
 
-    InvalidValue(Info, String),
+    /// Name of the undefined variable and the closest defined one
+    InvalidValue(Info, String, Option::<String>),
+
+    /// Name of the recursive variable that references itself
+    RecursiveVariable(Info, String),
+
+    /// Rest of the line starting at the `$(`, that is never closed
+    UnterminatedReference(Info, String),
+
+    InvalidFunctionCall(Info, String),
+
+    /// Name of the function that doesn't exist and the closest one that does
+    UnknownFunction(Info, String, Option::<String>),
+
+    /// Command passed to `$(shell ...)` and its error
+    ShellFailed(Info, String, String),
 
     InvalidUseOfFlag(String, Vec::<String>),
 
-    InvalidArgument(String, String),
+    /// Argument, available jobs and the closest job
+    InvalidArgument(String, String, Option::<String>),
+
+    /// Target of the job that failed or whose deps failed
+    NotRemade(String),
+
+    /// Operator the depfile is declared with, like: `+=`
+    InvalidDepfileOperator(Info, String),
+
+    /// Target whose recipe is overridden and location of the grouped job, like: `Rakefile:3`,
+    /// that produces it along with other targets.
+    GroupedTargetConflict(Info, String, String),
+
+    /// Targets forming the cycle with locations of their jobs,
+    /// the first target is repeated at the end.
+    DependencyCycle(Vec::<(String, Info)>),
 
-    InvalidScheisse,
+    /// Line that is neither a job, nor a variable declaration, nor a directive
+    UnrecognisedLine(Info, String),
+
+    /// Flag that is not in the table of flags and the closest one that is
+    UnknownFlag(String, Option::<String>),
+
+    /// All of the errors found while parsing the Rakefile
+    Multiple(Vec::<RakeError>),
 }
 
-impl Display for RakeError {
-    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+impl RakeError {
+    #[inline]
+    pub fn set_exit_code(code: i32) {
+        EXIT_CODE.store(code, Ordering::Relaxed)
+    }
+
+    fn info(&self) -> Option::<&Info> {
+        use RakeError::*;
+        match self {
+            FailedToExecute(info, ..)
+            | InvalidIndentation(info, ..)
+            | InvalidDependency(info, ..)
+            | UnmatchedDirective(info, ..)
+            | UnterminatedConditional(info)
+            | InvalidConditional(info, ..)
+            | IncludeNotFound(info, ..)
+            | RecursiveInclude(info, ..)
+            | DepsIndexOutOfBounds(info, ..)
+            | DepsSSwithoutDeps(info)
+            | NoTarget(info)
+            | MultipleNames(info)
+            | InvalidValue(info, ..)
+            | RecursiveVariable(info, ..)
+            | UnterminatedReference(info, ..)
+            | InvalidFunctionCall(info, ..)
+            | UnknownFunction(info, ..)
+            | ShellFailed(info, ..)
+            | InvalidDepfileOperator(info, ..)
+            | GroupedTargetConflict(info, ..)
+            | UnrecognisedLine(info, ..) => Some(info),
+            _ => None
+        }
+    }
+
+    fn help(&self) -> Option::<String> {
+        use RakeError::*;
+        match self {
+            InvalidIndentation(..)        => Some(format!("indent recipe lines with a tab or {EXPECTED_TAB_WIDTH} spaces")),
+            DepsIndexOutOfBounds(_, len)  => Some(format!("deps are indexed from 0, so the last valid index is {last}", last = len.saturating_sub(1))),
+            DepsSSwithoutDeps(..)         => Some("add dependencies after the `:` of the job".to_owned()),
+            InvalidValue(_, _, Some(sug)) => Some(format!("did you mean `{sug}`?")),
+            InvalidValue(_, name, None)   => Some(format!("define the variable like: `{name} = value`, or pass it like: `rake {name}=value`")),
+            UnknownFunction(_, _, Some(sug)) => Some(format!("did you mean `{sug}`?")),
+            UnknownFunction(_, _, None)   => Some(format!("available functions are: {funcs}", funcs = {
+                Func::ALL.iter().map(Func::name).collect::<Vec::<_>>().join(", ")
+            })),
+            RecursiveVariable(..)         => Some("use `:=` to expand the value once, at the definition".to_owned()),
+            UnterminatedReference(..)     => Some("add the missing `)`".to_owned()),
+            UnterminatedConditional(..)   => Some("add `endif` at the end of the conditional".to_owned()),
+            UnrecognisedLine(..)          => Some("recipe lines must be indented and follow a job, like: `target: deps`".to_owned()),
+            InvalidDepfileOperator(..)    => Some("declare the depfile like: `target: depfile = $t.d`".to_owned()),
+            GroupedTargetConflict(..)     => Some("override all of the grouped targets at once, with the same `&:` job".to_owned()),
+            _                             => None
+        }
+    }
+
+    fn fmt_message(&self, f: &mut Formatter) -> std::fmt::Result {
         use RakeError::*;
         match self {
             FailedToExecute(info, err)      => write!(f, "{f}:{r}: Failed to execute job:\n{err}", f = info.0, r = info.1),
             InvalidIndentation(info, w)     => write!(f, "{f}:{r}: Invalid indentation, expected: {EXPECTED_TAB_WIDTH}, got: {w}", f = info.0, r = info.1),
             InvalidDependency(info, dep)    => write!(f, "{f}:{r}: Dependency: `{dep}` is neither a defined job, nor an existing file, nor a directory", f = info.0, r = info.1),
-            NoRakefileInDir(dir)            => write!(f, "No Rakefile in: `{dir}`, you can specify path to dir with Rakefile using `-C` flag. For instance: `rake -C ./path_to_rakefile/`"),
+            NoRakefileInDir(dir)            => write!(f, "No Rakefile in: `{dir}` or any of its parents, you can specify path to dir with Rakefile using `-C` flag, or path to the Rakefile itself using `-f` flag. For instance: `rake -C ./path_to_rakefile/`"),
+            FailedToRead(path, err)         => write!(f, "Failed to read `{path}`: {err}"),
+            UnmatchedDirective(info, dir)   => write!(f, "{f}:{r}: `{dir}` without matching `ifeq`, `ifneq`, `ifdef` or `ifndef`", f = info.0, r = info.1),
+            UnterminatedConditional(info)   => write!(f, "{f}:{r}: Conditional is missing its `endif`", f = info.0, r = info.1),
+            InvalidConditional(info, cond)  => write!(f, "{f}:{r}: Invalid conditional: `{cond}`", f = info.0, r = info.1),
+            IncludeNotFound(info, file)     => write!(f, "{f}:{r}: Included file: `{file}` does not exist, use `-include` to skip missing files", f = info.0, r = info.1),
+            RecursiveInclude(info, file)    => write!(f, "{f}:{r}: File: `{file}` includes itself (eventually)", f = info.0, r = info.1),
             DepsIndexOutOfBounds(info, len) => write!(f, "{f}:{r}: Index out of bounds, NOTE: treat your deps as zero-indexed array. Length of your deps-array is: {len}", f = info.0, r = info.1),
             DepsSSwithoutDeps(info)         => write!(f, "{f}:{r}: Special `deps` syntax without deps", f = info.0, r = info.1),
             NoTarget(info)                  => write!(f, "{f}:{r}: Target is mandatory", f = info.0, r = info.1),
             MultipleNames(info)             => write!(f, "{f}:{r}: Provide only one name of the variable", f = info.0, r = info.1),
-            InvalidValue(info, value)       => write!(f, "{f}:{r}: Invalid value: {value}", f = info.0, r = info.1),
+            InvalidValue(info, name, _)     => write!(f, "{f}:{r}: Undefined variable: `{name}`", f = info.0, r = info.1),
+            RecursiveVariable(info, name)   => write!(f, "{f}:{r}: Recursive variable `{name}` references itself (eventually)", f = info.0, r = info.1),
+            UnterminatedReference(info, s)  => write!(f, "{f}:{r}: Unterminated variable reference: `{s}`", f = info.0, r = info.1),
+            UnknownFunction(info, name, _)  => write!(f, "{f}:{r}: Unknown function: `{name}`", f = info.0, r = info.1),
+            InvalidFunctionCall(info, err)  => write!(f, "{f}:{r}: Invalid function call: {err}", f = info.0, r = info.1),
+            ShellFailed(info, cmd, err)     => write!(f, "{f}:{r}: `$(shell {cmd})` failed:\n{err}", f = info.0, r = info.1),
             InvalidUseOfFlag(flag, args)    => write!(f, "Invalid use of flag: `{flag}`, arg: {args}", args = {
                 let joined = args.join(" ");
                 if joined.is_empty() { "[EMPTY]".to_owned() } else { joined }
             }),
-            InvalidArgument(arg, jobs)      => write!(f, "No such job or flag: `{arg}`. Availiable jobs to call: {jobs}, supported flags: -k, -s, -C"),
-            _                               => panic!("No way")
+            InvalidArgument(arg, _, Some(sug)) => write!(f, "No such job: `{arg}`, did you mean `{sug}`?"),
+            InvalidArgument(arg, jobs, None) => write!(f, "No such job: `{arg}`. Availiable jobs to call: {jobs}"),
+            NotRemade(target)               => write!(f, "Target `{target}` not remade because of errors"),
+            InvalidDepfileOperator(info, op) => write!(f, "{f}:{r}: Depfile can't be declared with `{op}`, use `=`, `:=` or `?=`", f = info.0, r = info.1),
+            GroupedTargetConflict(info, target, loc) => write!(f, "{f}:{r}: Recipe for target `{target}` conflicts with the grouped job at: {loc}", f = info.0, r = info.1),
+            DependencyCycle(cycle)          => {
+                let (_, info) = &cycle[0];
+                let path = cycle.iter().map(|(tar, _)| format!("`{tar}`")).collect::<Vec::<_>>().join(" -> ");
+                write!(f, "{f}:{r}: Dependency cycle detected: {path}", f = info.0, r = info.1)?;
+                for pair in cycle.windows(2) {
+                    let ((tar, info), (dep, _)) = (&pair[0], &pair[1]);
+                    write!(f, "\n    {f}:{r}: `{tar}` depends on `{dep}`", f = info.0, r = info.1)?;
+                }
+                Ok(())
+            }
+            UnrecognisedLine(info, line)    => write!(f, "{f}:{r}: Unrecognised line: `{line}`, expected a job, a variable declaration or a directive", f = info.0, r = info.1),
+            UnknownFlag(flag, Some(sug))    => write!(f, "Unknown flag: `{flag}`, did you mean `{sug}`?"),
+            UnknownFlag(flag, None)         => write!(f, "Unknown flag: `{flag}`, see `rake --help` for supported flags"),
+            Multiple(errs)                  => {
+                for (idx, err) in errs.iter().enumerate() {
+                    if idx > 0 { write!(f, "\n{lvl} ", lvl = LogLevel::PANIC)? }
+                    write!(f, "{err}")?;
+                }
+                write!(f, "\n{lvl} Found {n} errors", lvl = LogLevel::PANIC, n = errs.len())
+            }
         }
     }
 }
 
+impl Display for RakeError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        self.fmt_message(f)?;
+
+        let row = self.info().map_or(0, |info| info.1);
+        if let Some(span) = self.info().and_then(|info| info.2.as_ref()) {
+            span.render(f, row)?;
+        }
+
+        if let Some(help) = self.help() {
+            write!(f, "\n{pad} = help: {help}", pad = " ".repeat(row.to_string().len()))?;
+        }
+
+        Ok(())
+    }
+}
+
 // I decided to implement this kinda method to be able to report errors
 // in a more pretty and neat way in release mode, while keeping all the
 // necessary information about the caller and shit in debug mode.
@@ -108,7 +320,7 @@ where
                 if cfg!(debug_assertions) {
                     panic!("called `Option::unwrap()` on a `None` value")
                 } else {
-                    exit(1)
+                    exit(EXIT_CODE.load(Ordering::Relaxed))
                 }
             }
         }
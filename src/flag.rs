@@ -1,51 +1,293 @@
-use crate::RakeError;
+use std::{
+    num::NonZeroUsize,
+    fmt::{Display, Formatter}
+};
 
-// NOTE: Update `supported flags and all_flags constant` message if you updated this enum:
-pub enum Flag {
-    Keepgoing,
-    Silent,
-    Cd(String)
+use crate::{
+    RResult,
+    RakeError,
+    suggest
+};
+
+pub struct FlagSpec {
+    pub short: char,
+    pub long: &'static str,
+
+    /// Name of the argument to show in `--help`, if the flag takes one
+    pub arg: Option::<&'static str>,
+
+    pub help: &'static str
 }
 
-pub const ALL_FLAGS_STR: &[&str] = &["-k", "s", "-C"];
+// Generates the `Flag` enum along with the table of its specs, so that
+// parsing, `--help` and `Display` can never get out of sync with the enum.
+// Doc comments of the variants are used as their help messages.
+macro_rules! flags {
+    (@arg) => { None };
+    (@arg $arg: literal) => { Some($arg) };
+    ($($(#[doc = $help: literal])* $flag: ident $(($ty: ty, $arg: literal))?: $short: literal, $long: literal;)*) => {
+        #[derive(Debug)]
+        pub enum Flag {
+            $($(#[doc = $help])* $flag $(($ty))?,)*
+        }
 
-impl ToString for Flag {
-    fn to_string(&self) -> String {
-        use Flag::*;
-        let s = match self {
-            Keepgoing => "k",
-            Silent    => "t",
-            Cd(arg)   => &format!("C {arg}"),
-        };
-        format!("-{s}")
+        pub const FLAGS: &[FlagSpec] = &[$(
+            FlagSpec {
+                short: $short,
+                long: $long,
+                arg: flags!(@arg $($arg)?),
+                help: concat!($($help),*).trim_ascii()
+            },
+        )*];
+
+        impl Flag {
+            pub fn spec(&self) -> &'static FlagSpec {
+                let long = match self {
+                    $(Flag::$flag { .. } => $long,)*
+                };
+                Self::find_long(long).unwrap()
+            }
+
+            // Returns `None` if the argument of the flag is missing or invalid
+            fn new(spec: &FlagSpec, arg: Option::<&str>) -> Option::<Self> {
+                $(if spec.long == $long {
+                    return Some(Flag::$flag $((arg?.parse::<$ty>().ok()?))?)
+                })*
+                None
+            }
+        }
     }
 }
 
-type FlagAndArg = (String, Option::<String>);
+flags! {
+    /// Keep going when some jobs fail
+    Keepgoing: 'k', "keep-going";
 
-impl TryFrom::<FlagAndArg> for Flag {
-    type Error = RakeError;
+    /// Don't echo commands
+    Silent: 's', "silent";
 
-    fn try_from(farg: FlagAndArg) -> Result<Self, Self::Error> {
-        use Flag::*;
-        let (f, arg) = farg;
-        match f.as_str() {
-            "-k" => Ok(Keepgoing),
-            "-s" => Ok(Silent),
-            "-C" => if let Some(arg) = arg {
-                if arg.is_empty() {
-                    return Err(RakeError::InvalidUseOfFlag(f, vec![String::default()]))
-                }
+    /// Change to DIR before doing anything
+    Cd(String, "DIR"): 'C', "directory";
+
+    /// Read FILE as the Rakefile, `-` reads it from stdin
+    File(String, "FILE"): 'f', "file";
+
+    /// Print the commands that would be executed, without executing them
+    DryRun: 'n', "dry-run";
+
+    /// Consider every target out of date
+    AlwaysMake: 'B', "always-make";
+
+    /// Run nothing, exit with 0 if the goals are up to date, with 1 otherwise, and with 2 on errors
+    Question: 'q', "question";
+
+    /// Touch the targets instead of rebuilding them
+    Touch: 't', "touch";
+
+    /// Decide whether targets are out of date by contents of the files instead of their modification times
+    Hash: 'H', "hash";
+
+    /// Run up to N jobs at once
+    Jobs(NonZeroUsize, "N"): 'j', "jobs";
+
+    /// Print this message and exit
+    Help: 'h', "help";
+
+    /// Print the version and exit
+    Version: 'v', "version";
+}
+
+impl Display for Flag {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "-{short}", short = self.spec().short)
+    }
+}
+
+pub enum Arg {
+    Flag(Flag),
+
+    /// Goal or a variable override, like: `cc=clang`
+    Other(String)
+}
+
+impl Flag {
+    fn find_short(short: char) -> Option::<&'static FlagSpec> {
+        FLAGS.iter().find(|spec| spec.short == short)
+    }
+
+    fn find_long(long: &str) -> Option::<&'static FlagSpec> {
+        FLAGS.iter().find(|spec| spec.long == long)
+    }
+
+    fn unknown(flag: &str) -> RakeError {
+        let names = FLAGS.iter().flat_map(|spec| [format!("-{short}", short = spec.short), format!("--{long}", long = spec.long)]).collect::<Vec::<_>>();
+        let suggestion = suggest(flag, names.iter().map(String::as_str)).map(ToOwned::to_owned);
+        RakeError::UnknownFlag(flag.to_owned(), suggestion)
+    }
+
+    // `inline` is the argument passed like: `-Cdir`, `-C=dir` or `--directory=dir`,
+    // otherwise the argument is taken from the next one, like: `-C dir`.
+    fn with_arg<I>(spec: &FlagSpec, flag: &str, inline: Option::<&str>, args: &mut I) -> RResult::<Self>
+    where
+        I: Iterator::<Item = String>
+    {
+        let inline = inline.map(|inline| inline.strip_prefix('=').unwrap_or(inline));
+        let arg = match (spec.arg, inline) {
+            (None, Some(inline)) => return Err(RakeError::InvalidUseOfFlag(flag.to_owned(), vec![inline.to_owned()])),
+            (None, None) => None,
+            (Some(..), Some(inline)) => Some(inline.to_owned()),
+            // Next argument that looks like a flag is not taken as an argument, like in: `rake -C -k`,
+            // except for the plain `-`, like in: `rake -f -`
+            (Some(..), None) => args.next().filter(|arg| !arg.starts_with('-') || arg == "-")
+        };
+
+        let ret = match arg.as_deref() {
+            Some(arg) if !arg.is_empty() => Self::new(spec, Some(arg)),
+            Some(..) => None,
+            None => Self::new(spec, None)
+        };
+
+        ret.ok_or_else(|| RakeError::InvalidUseOfFlag(flag.to_owned(), vec![arg.unwrap_or_default()]))
+    }
 
-                let first = arg.chars().nth(0).unwrap() as u8;
-                match first {
-                    32..46 => Err(RakeError::InvalidUseOfFlag(f, vec![arg])),
-                    _      => Ok(Cd(arg.to_owned()))
+    // Parses command line arguments, flags can be written like: `-k`, `--keep-going`,
+    // clustered like: `-ks`, and everything after `--` is not a flag.
+    pub fn parse_args<I>(mut args: I) -> RResult::<Vec::<Arg>>
+    where
+        I: Iterator::<Item = String>
+    {
+        let mut ret = Vec::new();
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                ret.extend(args.by_ref().map(Arg::Other));
+                break
+            }
+
+            if let Some(long) = arg.strip_prefix("--") {
+                let (name, inline) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (long, None)
+                };
+                let spec = Self::find_long(name).ok_or_else(|| Self::unknown(&arg))?;
+                ret.push(Arg::Flag(Self::with_arg(spec, &arg, inline, &mut args)?));
+                continue
+            }
+
+            // Plain `-` is not a flag
+            let Some(shorts) = arg.strip_prefix('-').filter(|shorts| !shorts.is_empty()) else {
+                ret.push(Arg::Other(arg));
+                continue
+            };
+
+            for (idx, short) in shorts.char_indices() {
+                let spec = Self::find_short(short).ok_or_else(|| Self::unknown(&format!("-{short}")))?;
+
+                // Rest of the cluster is the argument of the flag, that takes one
+                let rest = &shorts[idx + short.len_utf8()..];
+                if !rest.is_empty() && (spec.arg.is_some() || rest.starts_with('=')) {
+                    ret.push(Arg::Flag(Self::with_arg(spec, &arg, Some(rest), &mut args)?));
+                    break
                 }
-            } else {
-                Err(RakeError::InvalidUseOfFlag(f, vec![String::default()]))
+
+                ret.push(Arg::Flag(Self::with_arg(spec, &format!("-{short}"), None, &mut args)?));
             }
-            _ => Err(RakeError::InvalidScheisse)
         }
+
+        Ok(ret)
+    }
+}
+
+pub fn help() -> String {
+    let usage = |spec: &FlagSpec| match spec.arg {
+        Some(arg) => format!("-{short}, --{long}={arg}", short = spec.short, long = spec.long),
+        None => format!("-{short}, --{long}", short = spec.short, long = spec.long)
+    };
+
+    let width = FLAGS.iter().map(|spec| usage(spec).len()).max().unwrap_or_default();
+    let mut ret = "Usage: rake [OPTION]... [TARGET]... [NAME=VALUE]...\n\nOptions:\n".to_owned();
+    for spec in FLAGS {
+        ret.push_str(&format!("  {usage:width$}    {help}\n", usage = usage(spec), help = spec.help));
+    }
+    ret
+}
+
+#[inline(always)]
+pub fn version() -> String {
+    format!("rake {version}", version = env!("CARGO_PKG_VERSION"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> RResult::<Vec::<Arg>> {
+        Flag::parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn cd(args: &[&str]) -> String {
+        match parse(args).unwrap().as_slice() {
+            [Arg::Flag(Flag::Cd(dir))] => dir.to_owned(),
+            _ => panic!("expected a single `-C` in: {args:?}")
+        }
+    }
+
+    #[test]
+    fn short_and_long() {
+        assert!(matches!(parse(&["-k"]).unwrap().as_slice(), [Arg::Flag(Flag::Keepgoing)]));
+        assert!(matches!(parse(&["--keep-going"]).unwrap().as_slice(), [Arg::Flag(Flag::Keepgoing)]));
+    }
+
+    #[test]
+    fn clusters() {
+        assert!(matches!(parse(&["-ks"]).unwrap().as_slice(), [Arg::Flag(Flag::Keepgoing), Arg::Flag(Flag::Silent)]));
+        assert!(matches!(
+            parse(&["-kCdir"]).unwrap().as_slice(),
+            [Arg::Flag(Flag::Keepgoing), Arg::Flag(Flag::Cd(dir))] if dir == "dir"
+        ));
+    }
+
+    #[test]
+    fn flag_arguments() {
+        for args in [&["-Cdir"][..], &["-C=dir"], &["-C", "dir"], &["--directory=dir"], &["--directory", "dir"]] {
+            assert_eq!(cd(args), "dir", "{args:?}");
+        }
+        assert!(matches!(parse(&["-j4"]).unwrap().as_slice(), [Arg::Flag(Flag::Jobs(n))] if n.get() == 4));
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert!(matches!(parse(&["-k=1"]), Err(RakeError::InvalidUseOfFlag(..))));
+        assert!(matches!(parse(&["--keep-going=1"]), Err(RakeError::InvalidUseOfFlag(..))));
+        assert!(matches!(parse(&["-j0"]), Err(RakeError::InvalidUseOfFlag(..))));
+        assert!(matches!(parse(&["-C"]), Err(RakeError::InvalidUseOfFlag(..))));
+        assert!(matches!(parse(&["-C", "-k"]), Err(RakeError::InvalidUseOfFlag(..))));
+    }
+
+    #[test]
+    fn stdin_file() {
+        assert!(matches!(parse(&["-f", "-"]).unwrap().as_slice(), [Arg::Flag(Flag::File(f))] if f == "-"));
+        assert!(matches!(parse(&["-"]).unwrap().as_slice(), [Arg::Other(arg)] if arg == "-"));
+    }
+
+    #[test]
+    fn double_dash() {
+        assert!(matches!(
+            parse(&["-k", "--", "-s", "all"]).unwrap().as_slice(),
+            [Arg::Flag(Flag::Keepgoing), Arg::Other(a), Arg::Other(b)] if a == "-s" && b == "all"
+        ));
+    }
+
+    #[test]
+    fn goals_and_overrides() {
+        assert!(matches!(
+            parse(&["all", "cc=clang"]).unwrap().as_slice(),
+            [Arg::Other(a), Arg::Other(b)] if a == "all" && b == "cc=clang"
+        ));
+    }
+
+    #[test]
+    fn unknown_flags() {
+        assert!(matches!(parse(&["-x"]), Err(RakeError::UnknownFlag(flag, None)) if flag == "-x"));
+        assert!(matches!(parse(&["--kep-going"]), Err(RakeError::UnknownFlag(_, Some(sug))) if sug == "--keep-going"));
     }
 }
@@ -0,0 +1,321 @@
+use std::{
+    fs::read_dir,
+    path::Path,
+    process::Command
+};
+use robuild::{CMD_ARG, CMD_ARG2};
+
+// Built-in functions, called like: `$(patsubst %.c,%.o,$(srcs))`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Func {
+    Wildcard,
+    Patsubst,
+    Subst,
+    Filter,
+    FilterOut,
+    Sort,
+    Dir,
+    Notdir,
+    Basename,
+    Suffix,
+    Addprefix,
+    Addsuffix,
+    Words,
+    Word,
+    Strip,
+
+    // These are evaluated by the `Rakefile` itself: `foreach` and `if` are expanded lazily,
+    // and failure of `shell` is reported with its own error.
+    Foreach,
+    If,
+    Shell
+}
+
+impl Func {
+    pub const ALL: &'static [Func] = {
+        use Func::*;
+        &[
+            Wildcard, Patsubst, Subst, Filter, FilterOut, Sort, Dir, Notdir, Basename,
+            Suffix, Addprefix, Addsuffix, Words, Word, Strip, Foreach, If, Shell
+        ]
+    };
+
+    pub fn from_name(name: &str) -> Option::<Self> {
+        use Func::*;
+        match name {
+            "wildcard"   => Some(Wildcard),
+            "patsubst"   => Some(Patsubst),
+            "subst"      => Some(Subst),
+            "filter"     => Some(Filter),
+            "filter-out" => Some(FilterOut),
+            "sort"       => Some(Sort),
+            "dir"        => Some(Dir),
+            "notdir"     => Some(Notdir),
+            "basename"   => Some(Basename),
+            "suffix"     => Some(Suffix),
+            "addprefix"  => Some(Addprefix),
+            "addsuffix"  => Some(Addsuffix),
+            "words"      => Some(Words),
+            "word"       => Some(Word),
+            "strip"      => Some(Strip),
+            "foreach"    => Some(Foreach),
+            "if"         => Some(If),
+            "shell"      => Some(Shell),
+            _            => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        use Func::*;
+        match self {
+            Wildcard  => "wildcard",
+            Patsubst  => "patsubst",
+            Subst     => "subst",
+            Filter    => "filter",
+            FilterOut => "filter-out",
+            Sort      => "sort",
+            Dir       => "dir",
+            Notdir    => "notdir",
+            Basename  => "basename",
+            Suffix    => "suffix",
+            Addprefix => "addprefix",
+            Addsuffix => "addsuffix",
+            Words     => "words",
+            Word      => "word",
+            Strip     => "strip",
+            Foreach   => "foreach",
+            If        => "if",
+            Shell     => "shell"
+        }
+    }
+
+    // Minimal and maximal number of arguments, the last argument takes
+    // the rest of the input, commas included, just like in Makefile.
+    pub fn arity(&self) -> (usize, usize) {
+        use Func::*;
+        match self {
+            Wildcard | Sort | Dir | Notdir | Basename | Suffix | Words | Strip | Shell => (1, 1),
+            Filter | FilterOut | Addprefix | Addsuffix | Word => (2, 2),
+            Patsubst | Subst | Foreach => (3, 3),
+            If => (2, 3)
+        }
+    }
+
+    // Splits raw arguments by the commas, that are not inside of `$(...)`
+    pub fn split_args(&self, args: &str) -> Vec::<String> {
+        let max = self.arity().1;
+        let mut ret = Vec::new();
+        let (mut depth, mut start) = (0usize, 0);
+        for (idx, c) in args.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' => depth = depth.saturating_sub(1),
+                ',' if depth == 0 && ret.len() + 1 < max => {
+                    ret.push(args[start..idx].to_owned());
+                    start = idx + 1;
+                }
+                _ => {}
+            }
+        }
+        ret.push(args[start..].to_owned());
+        ret
+    }
+
+    // Applies the function to the already expanded arguments
+    pub fn apply(&self, args: &[String]) -> Result::<String, String> {
+        use Func::*;
+
+        let words = |idx: usize| args[idx].split_whitespace();
+        let ret = match self {
+            Wildcard => words(0).flat_map(glob).collect::<Vec::<_>>().join(" "),
+            Patsubst => {
+                let (pattern, replacement) = (args[0].trim(), args[1].trim());
+                words(2).map(|word| patsubst(pattern, replacement, word)).collect::<Vec::<_>>().join(" ")
+            }
+            Subst => if args[0].is_empty() {
+                args[2].to_owned()
+            } else {
+                args[2].replace(&args[0], &args[1])
+            }
+            Filter | FilterOut => {
+                let patterns = words(0).collect::<Vec::<_>>();
+                words(1).filter(|word| {
+                    patterns.iter().any(|pattern| matches_pattern(pattern, word)) == (*self == Filter)
+                }).collect::<Vec::<_>>().join(" ")
+            }
+            Sort => {
+                let mut words = words(0).collect::<Vec::<_>>();
+                words.sort_unstable();
+                words.dedup();
+                words.join(" ")
+            }
+            Dir => words(0).map(|word| match word.rfind('/') {
+                Some(idx) => &word[..=idx],
+                None => "./"
+            }).collect::<Vec::<_>>().join(" "),
+            Notdir => words(0).map(|word| match word.rfind('/') {
+                Some(idx) => &word[idx + 1..],
+                None => word
+            }).collect::<Vec::<_>>().join(" "),
+            Basename => words(0).map(|word| match suffix_idx(word) {
+                Some(idx) => &word[..idx],
+                None => word
+            }).collect::<Vec::<_>>().join(" "),
+            Suffix => words(0).filter_map(|word| {
+                suffix_idx(word).map(|idx| &word[idx..])
+            }).collect::<Vec::<_>>().join(" "),
+            Addprefix => words(1).map(|word| format!("{prefix}{word}", prefix = args[0].trim())).collect::<Vec::<_>>().join(" "),
+            Addsuffix => words(1).map(|word| format!("{word}{suffix}", suffix = args[0].trim())).collect::<Vec::<_>>().join(" "),
+            Words => words(0).count().to_string(),
+            Word => {
+                let n = args[0].trim().parse::<usize>().ok().filter(|n| *n > 0).ok_or_else(|| {
+                    format!("first argument of `word` must be a positive number, got: `{n}`", n = args[0].trim())
+                })?;
+                words(1).nth(n - 1).unwrap_or_default().to_owned()
+            }
+            Strip => words(0).collect::<Vec::<_>>().join(" "),
+            Foreach | If | Shell => unreachable!("`{name}` is evaluated by the `Rakefile`", name = self.name())
+        };
+
+        Ok(ret)
+    }
+}
+
+// Runs the command through the shell and returns its output with newlines turned into spaces,
+// trailing ones are removed, just like in Makefile.
+pub fn shell(cmd: &str) -> Result::<String, String> {
+    let out = Command::new(CMD_ARG).arg(CMD_ARG2).arg(cmd).output().map_err(|err| err.to_string())?;
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    if !out.status.success() {
+        let err = stderr.trim_end();
+        return Err(if err.is_empty() {
+            match out.status.code() {
+                Some(code) => format!("exited abnormally with code: {code}"),
+                None => "terminated by signal".to_owned()
+            }
+        } else {
+            err.to_owned()
+        })
+    }
+
+    // Let warnings of the successful command through
+    eprint!("{stderr}");
+
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    Ok(stdout.trim_end_matches(['\n', '\r']).replace("\r\n", " ").replace('\n', " "))
+}
+
+// Returns the stem if the name matches the pattern, e.g.
+// `build/foo.o` matches `build/%.o` with the stem: `foo`.
+pub fn match_stem<'s>(pattern: &str, name: &'s str) -> Option::<&'s str> {
+    let (prefix, suffix) = pattern.split_once('%')?;
+    name.strip_prefix(prefix)?
+        .strip_suffix(suffix)
+        .filter(|stem| !stem.is_empty())
+}
+
+// Pattern without `%` matches only the exact word
+fn matches_pattern(pattern: &str, word: &str) -> bool {
+    if pattern.contains('%') {
+        match_stem(pattern, word).is_some()
+    } else {
+        pattern == word
+    }
+}
+
+fn patsubst(pattern: &str, replacement: &str, word: &str) -> String {
+    if !pattern.contains('%') {
+        return if pattern == word { replacement.to_owned() } else { word.to_owned() }
+    }
+
+    match match_stem(pattern, word) {
+        Some(stem) => replacement.replacen('%', stem, 1),
+        None => word.to_owned()
+    }
+}
+
+// Index of the `.` of the suffix, if the last path component has one
+fn suffix_idx(word: &str) -> Option::<usize> {
+    let idx = word.rfind('.')?;
+    (!word[idx..].contains('/')).then_some(idx)
+}
+
+// Glob matching of a single path component, supports: `*`, `?` and `[...]`
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            glob_match(&pattern[1..], name) || (!name.is_empty() && glob_match(pattern, &name[1..]))
+        }
+        (Some('?'), Some(_)) => glob_match(&pattern[1..], &name[1..]),
+        (Some('['), Some(c)) => {
+            let Some(end) = pattern.iter().skip(1).position(|x| *x == ']').map(|end| end + 1) else {
+                return *c == '[' && glob_match(&pattern[1..], &name[1..])
+            };
+
+            let (negate, class) = match pattern[1] {
+                '!' | '^' => (true, &pattern[2..end]),
+                _ => (false, &pattern[1..end])
+            };
+
+            let mut matched = false;
+            let mut idx = 0;
+            while idx < class.len() {
+                if idx + 2 < class.len() && class[idx + 1] == '-' {
+                    matched |= (class[idx]..=class[idx + 2]).contains(c);
+                    idx += 3;
+                } else {
+                    matched |= class[idx] == *c;
+                    idx += 1;
+                }
+            }
+
+            matched != negate && glob_match(&pattern[end + 1..], &name[1..])
+        }
+        (Some(p), Some(c)) => p == c && glob_match(&pattern[1..], &name[1..]),
+        _ => false
+    }
+}
+
+// Expands the glob pattern into the sorted list of existing paths
+pub fn glob(pattern: &str) -> Vec::<String> {
+    if !pattern.contains(['*', '?', '[']) {
+        return if Path::new(pattern).exists() { vec![pattern.to_owned()] } else { Vec::new() }
+    }
+
+    let (mut paths, components) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec!["/".to_owned()], rest),
+        None => (vec![String::new()], pattern)
+    };
+
+    for component in components.split('/').filter(|c| !c.is_empty()) {
+        let chars = component.chars().collect::<Vec::<_>>();
+        paths = paths.into_iter().flat_map(|dir| {
+            let join = |name: &str| if dir.is_empty() || dir.ends_with('/') {
+                format!("{dir}{name}")
+            } else {
+                format!("{dir}/{name}")
+            };
+
+            if !component.contains(['*', '?', '[']) {
+                return vec![join(component)]
+            }
+
+            let Ok(entries) = read_dir(if dir.is_empty() { "." } else { &dir }) else {
+                return Vec::new()
+            };
+
+            entries.filter_map(|entry| entry.ok())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                // Hidden files are matched only explicitly
+                .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+                .filter(|name| glob_match(&chars, &name.chars().collect::<Vec::<_>>()))
+                .map(|name| join(&name))
+                .collect()
+        }).collect();
+    }
+
+    let mut paths = paths.into_iter().filter(|path| Path::new(path).exists()).collect::<Vec::<_>>();
+    paths.sort_unstable();
+    paths
+}
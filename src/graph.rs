@@ -0,0 +1,149 @@
+use std::collections::HashMap;
+
+use crate::{
+    Info,
+    RResult,
+    Rakefile,
+    RakeError
+};
+
+pub type NodeId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeKind {
+    /// Index of the job in `Rakefile::jobs`
+    Job(usize),
+
+    /// Source file (or directory) that no job produces
+    File
+}
+
+#[derive(Debug)]
+pub struct Node {
+    pub name: String,
+    pub kind: NodeKind,
+
+    pub deps: Vec::<NodeId>,
+    pub dependents: Vec::<NodeId>
+}
+
+// Build graph: nodes for targets and source files, edges for deps.
+// It's built once the whole Rakefile is parsed, so order of the job declarations does not matter.
+#[derive(Debug, Default)]
+pub struct Graph {
+    pub nodes: Vec::<Node>,
+    ids: HashMap::<String, NodeId>
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mark {
+    Unvisited,
+    InProgress,
+    Visited
+}
+
+impl Graph {
+    pub fn new(rake: &Rakefile) -> RResult::<Self> {
+        let mut graph = Self::default();
+
+        for (idx, job) in rake.jobs.iter().enumerate() {
+            let id = graph.add_node(&job.target);
+            graph.nodes[id].kind = NodeKind::Job(idx);
+
+            // Grouped outputs share the node of the job, so it's executed once for all of them
+            for output in job.outputs.iter().skip(1) {
+                graph.ids.insert(output.to_owned(), id);
+            }
+        }
+
+        for job in rake.jobs.iter() {
+            let id = graph.ids[&job.target];
+            for dep in job.deps.iter() {
+                let dep_id = graph.add_node(dep);
+                graph.nodes[id].deps.push(dep_id);
+                graph.nodes[dep_id].dependents.push(id);
+            }
+        }
+
+        graph.check_cycles(rake)?;
+        Ok(graph)
+    }
+
+    fn add_node(&mut self, name: &str) -> NodeId {
+        if let Some(id) = self.ids.get(name) {
+            return *id
+        }
+
+        let id = self.nodes.len();
+        self.ids.insert(name.to_owned(), id);
+        self.nodes.push(Node {
+            name: name.to_owned(),
+            kind: NodeKind::File,
+            deps: Vec::new(),
+            dependents: Vec::new()
+        });
+        id
+    }
+
+    #[inline(always)]
+    pub fn id(&self, name: &str) -> Option::<NodeId> {
+        self.ids.get(name).copied()
+    }
+
+    fn check_cycles(&self, rake: &Rakefile) -> RResult::<()> {
+        let mut marks = vec![Mark::Unvisited; self.nodes.len()];
+        for id in 0..self.nodes.len() {
+            if marks[id] != Mark::Unvisited { continue }
+
+            let mut path = Vec::new();
+            if let Some(cycle) = self.find_cycle(id, &mut marks, &mut path) {
+                let cycle = cycle.into_iter().map(|id| {
+                    let node = &self.nodes[id];
+                    let info = match node.kind {
+                        NodeKind::Job(idx) => rake.jobs[idx].info.to_owned(),
+                        NodeKind::File => Info::from(rake)
+                    };
+                    (node.name.to_owned(), info)
+                }).collect();
+                return Err(RakeError::DependencyCycle(cycle))
+            }
+        }
+        Ok(())
+    }
+
+    // Iterative DFS, so deep graphs don't blow up the stack.
+    // Returns the path of the cycle, with the first node repeated at the end.
+    fn find_cycle(&self, root: NodeId, marks: &mut [Mark], path: &mut Vec::<NodeId>) -> Option::<Vec::<NodeId>> {
+        let mut stack = vec![(root, 0)];
+        marks[root] = Mark::InProgress;
+        path.push(root);
+
+        while let Some((id, next)) = stack.last_mut() {
+            let id = *id;
+            let Some(dep) = self.nodes[id].deps.get(*next).copied() else {
+                marks[id] = Mark::Visited;
+                path.pop();
+                stack.pop();
+                continue
+            };
+
+            *next += 1;
+            match marks[dep] {
+                Mark::Visited => {}
+                Mark::InProgress => {
+                    let start = path.iter().position(|x| *x == dep).unwrap();
+                    let mut cycle = path[start..].to_vec();
+                    cycle.push(dep);
+                    return Some(cycle)
+                }
+                Mark::Unvisited => {
+                    marks[dep] = Mark::InProgress;
+                    path.push(dep);
+                    stack.push((dep, 0));
+                }
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,47 @@
+// Edit distance between the strings, where swapping of two adjacent chars counts as a single edit,
+// so that typos like: `biuld` are as close to `build` as `buld` is.
+pub fn distance(a: &str, b: &str) -> usize {
+    let (a, b) = (a.chars().collect::<Vec::<_>>(), b.chars().collect::<Vec::<_>>());
+
+    // Three last rows of the table are enough
+    let mut prev2 = vec![0; b.len() + 1];
+    let mut prev = (0..=b.len()).collect::<Vec::<_>>();
+    let mut curr = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        curr[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            curr[j] = (prev[j] + 1).min(curr[j - 1] + 1).min(prev[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                curr[j] = curr[j].min(prev2[j - 2] + 1);
+            }
+        }
+        (prev2, prev, curr) = (prev, curr, prev2);
+    }
+
+    prev[b.len()]
+}
+
+// Returns the closest candidate, if it's close enough to be a typo of the name.
+// Candidates that differ only in case win, like `-K` -> `-k`, otherwise names shorter
+// than 3 chars are not suggested for, since every short name is close to any other one.
+// Leading dashes of the flags are not a part of the name, so `-x` is not a typo of `-k`.
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator::<Item = &'a str>) -> Option::<&'a str> {
+    const MIN_LEN: usize = 3;
+
+    let (full, name) = (name, name.trim_start_matches('-'));
+    let len = name.chars().count();
+    let max = (len / 3).max(1);
+    candidates.into_iter()
+        .filter(|candidate| *candidate != full)
+        .filter_map(|candidate| {
+            let stripped = candidate.trim_start_matches('-');
+            if stripped.eq_ignore_ascii_case(name) { return Some((0, candidate)) }
+            if len < MIN_LEN { return None }
+
+            let dist = distance(name, stripped);
+            (dist <= max).then_some((dist, candidate))
+        })
+        .min_by_key(|(dist, _)| *dist)
+        .map(|(_, candidate)| candidate)
+}
@@ -0,0 +1,165 @@
+use std::{
+    fs::OpenOptions,
+    time::SystemTime,
+    process::Command
+};
+use robuild::*;
+
+use crate::{
+    Db,
+    Info,
+    RResult,
+    RakeError
+};
+
+// Recipe line with make-like prefixes stripped off
+pub struct RLine<'a> {
+    pub cmd: &'a str,
+
+    /// `@`: don't echo the line, even if the job echoes
+    pub silent: bool,
+
+    /// `-`: ignore non-zero exit code of the line and keep going
+    pub ignore: bool,
+
+    /// `+`: execute the line even in modes that don't execute recipes
+    pub force: bool
+}
+
+impl<'a> RLine<'a> {
+    pub fn parse(line: &'a str) -> Self {
+        let mut rline = RLine { cmd: line, silent: false, ignore: false, force: false };
+        loop {
+            let cmd = rline.cmd.trim_start();
+            match cmd.chars().next() {
+                Some('@') => rline.silent = true,
+                Some('-') => rline.ignore = true,
+                Some('+') => rline.force = true,
+                _ => break
+            }
+            rline.cmd = &cmd[1..];
+        }
+        rline.cmd = rline.cmd.trim_start();
+        rline
+    }
+}
+
+// How the recipes of the stale jobs are handled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Execute,
+
+    /// `-n`: print the commands instead of executing them
+    DryRun,
+
+    /// `-t`: touch the targets instead of executing the commands
+    Touch,
+
+    /// `-q`: execute nothing, only check whether the goals are up to date
+    Question
+}
+
+#[derive(Debug, Clone)]
+pub struct RJob {
+    pub target: String,
+
+    // All of the files produced by a single execution of the job, starting with the `target`,
+    // more than one only for grouped targets, like: `a.tab.c a.tab.h &: a.y`.
+    pub outputs: Vec::<String>,
+
+    pub deps: Vec::<String>,
+
+    // Already expanded recipe lines
+    pub body: Vec::<String>,
+
+    // Rows and source text of the body lines of pattern rules, since their
+    // special symbols are expanded only once the rule gets instantiated.
+    pub sources: Vec::<(usize, String)>,
+
+    pub cfg: Config,
+    pub info: Info,
+
+    // Path of the depfile the recipe writes, like: `build/foo.o.d`, see: `depfile.rs`
+    pub depfile: Option::<String>
+}
+
+impl RJob {
+    #[inline]
+    pub fn new(outputs: &[&str], deps: Vec::<&str>, body: Vec::<String>, cfg: Config, info: Info) -> Self {
+        let outputs = outputs.iter().map(|output| output.to_string()).collect::<Vec::<_>>();
+        let target = outputs[0].to_owned();
+        let deps = deps.into_iter().map(ToOwned::to_owned).collect();
+        Self { target, outputs, deps, body, sources: Vec::new(), cfg, info, depfile: None }
+    }
+
+    #[inline]
+    pub fn is_grouped(&self) -> bool {
+        self.outputs.len() > 1
+    }
+
+    // Hash of the commands, prefixes of the lines don't affect it
+    pub fn cmd_hash(&self) -> u64 {
+        Db::hash(self.body.iter().map(|line| RLine::parse(line).cmd))
+    }
+
+    fn format_out(out: &str) -> &str {
+        out.strip_suffix('\n').unwrap_or(out)
+    }
+
+    // Lines are executed one after another, the first failing line fails the whole job,
+    // unless the line is prefixed with `-`.
+    // Running jobs concurrently is up to the scheduler, see: `sched.rs`.
+    pub fn execute(&self, env: &[(String, String)], mode: Mode) -> RResult::<()> {
+        for line in self.body.iter() {
+            let RLine { cmd: line, silent, ignore, force } = RLine::parse(line);
+            if line.is_empty() { continue }
+
+            // Lines prefixed with `+` are executed in every mode
+            if mode != Mode::Execute && !force {
+                if mode == Mode::DryRun { log!(CMD, "{line}"); }
+                continue
+            }
+
+            if self.cfg.echo && !silent { log!(CMD, "{line}"); }
+
+            let out = Command::new(CMD_ARG)
+                .arg(CMD_ARG2)
+                .arg(line)
+                .envs(env.iter().map(|(name, value)| (name, value)))
+                .output()
+                .map_err(|err| RakeError::FailedToExecute(self.info.to_owned(), err.to_string()))?;
+
+            let stdout = String::from_utf8_lossy(&out.stdout);
+            if !stdout.is_empty() && self.cfg.echo {
+                log!(INFO, "{out}", out = Self::format_out(&stdout));
+            }
+
+            if !out.status.success() && ignore {
+                let code = out.status.code().map(|code| code.to_string()).unwrap_or_else(|| "signal".to_owned());
+                log!(WARN, "{f}:{r}: `{line}` exited abnormally with code: {code} (ignored)", f = self.info.0, r = self.info.1);
+            } else if !out.status.success() {
+                let stderr = String::from_utf8_lossy(&out.stderr);
+                let err = match out.status.code() {
+                    _ if !stderr.is_empty() => Self::format_out(&stderr).to_owned(),
+                    Some(code) => format!("`{line}` exited abnormally with code: {code}"),
+                    None => format!("`{line}` terminated by signal")
+                };
+                return Err(RakeError::FailedToExecute(self.info.to_owned(), err))
+            }
+        }
+
+        Ok(())
+    }
+
+    // Updates modification times of the outputs, creating the ones that don't exist
+    pub fn touch(&self) -> RResult::<()> {
+        for output in self.outputs.iter() {
+            if self.cfg.echo { log!(CMD, "touch {output}"); }
+
+            OpenOptions::new().create(true).append(true).open(output)
+                .and_then(|file| file.set_modified(SystemTime::now()))
+                .map_err(|err| RakeError::FailedToExecute(self.info.to_owned(), err.to_string()))?;
+        }
+        Ok(())
+    }
+}
@@ -1,15 +1,18 @@
 use std::{
+    io,
     env,
+    vec,
+    mem,
     result,
-    str::Lines,
-    path::PathBuf,
+    borrow::Cow,
     sync::LazyLock,
+    path::{Path, PathBuf},
     iter::Peekable,
-    process::Output,
     default::Default,
+    io::Read,
+    process::exit,
     fs::read_to_string,
     collections::{
-        VecDeque,
         HashSet,
         HashMap
     }
@@ -19,68 +22,176 @@ use robuild::*;
 
 mod ss;
 mod ct;
+mod job;
 mod cfg;
+mod db;
+mod depfile;
+mod cond;
 mod flag;
+mod func;
+mod hint;
+mod vars;
+mod graph;
+mod sched;
 mod error;
 
 use ss::*;
 use ct::*;
+use job::*;
 use cfg::*;
+use db::*;
+use depfile::*;
+use cond::*;
 use flag::*;
+use func::*;
+use hint::*;
+use vars::*;
+use graph::*;
+use sched::*;
 use error::*;
 
 type RResult<T> = result::Result::<T, RakeError>;
 
-#[derive(Debug, Clone)]
-struct RJob(Job, Info);
+// Lines are owned, so that included files can be parsed with the same `Rakefile`
+type LinesIter = Peekable::<vec::IntoIter::<String>>;
 
-const DEPS_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\$d\[(.*?)\]").unwrap());
-const VARS_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\$\((.*?)\)").unwrap());
+static DEPS_REGEX: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\$d\[(.*?)\]").unwrap());
 
-struct Rakefile<'a> {
+struct Rakefile {
     row: usize,
 
+    // Source line being parsed right now, to point errors at
+    line: String,
+
     file_path: PathBuf,
 
-    jobs: VecDeque::<RJob>,
+    jobs: Vec::<RJob>,
     jobmap: HashMap::<String, usize>,
 
+    // Pattern rules, like: `build/%.o: src/%.c`. Their bodies are kept
+    // with special symbols unexpanded, until the rule gets instantiated.
+    patterns: Vec::<RJob>,
+
+    graph: Graph,
+
+    // Targets marked with `.PHONY` or `.ALWAYS`, stored separately so
+    // they can be declared before the job itself.
+    phony: HashSet::<String>,
+
+    // Targets listed in `.RAKE_HASH`, `hash_all` is set by `.RAKE_HASH` without deps
+    hashed: HashSet::<String>,
+    hash_all: bool,
+
+    // Depfiles declared like: `build/%.o: depfile = $t.d` or with `.DEPFILE`,
+    // keyed by a target or a pattern, values are kept with special symbols unexpanded.
+    depfiles: HashMap::<String, Depfile>,
+
     comptime: Comptime,
 
-    vars: HashMap::<&'a str, &'a str>,
+    vars: HashMap::<String, Var>,
+
+    // Names of the variables passed to the environment of every recipe command: `export NAME`,
+    // along with locations and source lines of their `export`s to point errors at.
+    exports: HashMap::<String, (Info, String)>,
 
-    iter: Peekable::<Lines<'a>>
+    // Stack of the conditional directives we're inside of
+    conds: Vec::<Cond>,
+
+    // Canonical paths of the files being parsed right now, to catch recursive includes
+    including: Vec::<PathBuf>,
+
+    // Errors found while parsing
+    errors: Vec::<RakeError>,
+
+    iter: LinesIter
 }
 
-impl Default for Rakefile<'_> {
+impl Default for Rakefile {
     fn default() -> Self {
         Self {
             row: 1,
             file_path: PathBuf::default(),
-            jobs: VecDeque::default(),
+            jobs: Vec::default(),
             jobmap: HashMap::default(),
+            patterns: Vec::default(),
+            graph: Graph::default(),
+            phony: HashSet::default(),
+            hashed: HashSet::default(),
+            hash_all: false,
+            depfiles: HashMap::default(),
             vars: HashMap::default(),
+            exports: HashMap::default(),
             comptime: Comptime::default(),
-            iter: "".lines().peekable(),
+            conds: Vec::default(),
+            including: Vec::default(),
+            errors: Vec::default(),
+            line: String::default(),
+            iter: Self::lines(""),
         }
     }
 }
 
-impl<'a> Rakefile<'a> {
+impl Rakefile {
     pub const TAB_WIDTH: usize = 4;
     pub const MAX_DIR_LVL: usize = 3;
 
-    pub const RAKE_FILE_NAME: &'static str = "Rakefile";
+    // Name of the per-job declaration, like: `build/%.o: depfile = $t.d`
+    pub const DEPFILE_VAR: &'static str = "depfile";
+
+    // Looked up in this order, unless a file is specified with `-f`
+    pub const RAKE_FILE_NAMES: &'static [&'static str] = &["Rakefile", "rakefile", "Rakefile.rake"];
+
+    // Passed like: `-f -`, to read the Rakefile from stdin
+    pub const STDIN_FILE_NAME: &'static str = "-";
+
+    #[inline(always)]
+    fn lines(file_str: &str) -> LinesIter {
+        file_str.lines().map(ToOwned::to_owned).collect::<Vec::<_>>().into_iter().peekable()
+    }
 
-    fn find_rakefile() -> RResult::<PathBuf> {
+    // Looks for the Rakefile in the current directory and then in its parents, up to the root,
+    // like cargo does. If it's found in one of the parents, that directory is entered.
+    fn find_rakefile(comptime: &mut Comptime) -> RResult::<PathBuf> {
         let dir_path = env::current_dir().unwrap_or_report();
-        let pretty_path = Self::pretty_path(&dir_path);
-        Dir::new(&dir_path).into_iter()
-            .find(|f| matches!(f.file_name(), Some(name) if name == Self::RAKE_FILE_NAME))
-            .ok_or_else(move || RakeError::NoRakefileInDir(pretty_path))
+        let found = dir_path.ancestors().find_map(|dir| {
+            Self::RAKE_FILE_NAMES.iter()
+                .map(|name| dir.join(name))
+                .find(|path| path.is_file())
+        });
+
+        let Some(file_path) = found else {
+            return Err(RakeError::NoRakefileInDir(Self::pretty_path(&dir_path)))
+        };
+
+        if let Some(dir) = file_path.parent().filter(|dir| *dir != dir_path) {
+            comptime.enter_dir(dir.display().to_string());
+        }
+
+        Ok(file_path)
     }
 
-    fn pretty_path(file_path: &PathBuf) -> String {
+    // Returns path of the Rakefile and its contents
+    fn read_rakefile(comptime: &mut Comptime) -> RResult::<(PathBuf, String)> {
+        let file_path = match comptime.rcfg().if_file() {
+            Some(file) if file == Self::STDIN_FILE_NAME => {
+                let mut file_str = String::new();
+                io::stdin().read_to_string(&mut file_str).map_err(|err| {
+                    RakeError::FailedToRead("<stdin>".to_owned(), err.to_string())
+                })?;
+                return Ok((PathBuf::from("<stdin>"), file_str))
+            }
+            Some(file) => PathBuf::from(file),
+            None => Self::find_rakefile(comptime)?
+        };
+
+        let file_str = read_to_string(&file_path).map_err(|err| {
+            RakeError::FailedToRead(file_path.display().to_string(), err.to_string())
+        })?;
+
+        Ok((file_path, file_str))
+    }
+
+    fn pretty_path(file_path: &Path) -> String {
         let mut count = 0;
         let string = file_path.display().to_string();
         string.chars().rev().take_while(|c| {
@@ -89,30 +200,42 @@ impl<'a> Rakefile<'a> {
         }).collect::<Vec::<_>>().into_iter().rev().collect()
     }
 
-    fn append_job(&mut self, job: RJob) {
-        let key = job.0.target();
+    fn append_job(&mut self, job: RJob) -> RResult::<()> {
+        let key = &job.target;
+
+        // Replace the old job in place, so indices stored in the `jobmap` stay valid.
+        // Grouped targets can be overridden only all at once, so no job is left producing a part of them.
+        if let Some((output, idx)) = job.outputs.iter().find_map(|output| self.jobmap.get(output).map(|idx| (output, *idx))) {
+            let old_job = &self.jobs[idx];
+            if (job.is_grouped() || old_job.is_grouped()) && job.outputs != old_job.outputs {
+                let grouped = if old_job.is_grouped() { &old_job.info } else { &job.info };
+                let loc = format!("{f}:{r}", f = grouped.0, r = grouped.1);
+                return Err(RakeError::GroupedTargetConflict(job.info.to_owned(), output.to_owned(), loc))
+            }
 
-        if let Some(idx) = self.jobmap.get(key) {
-            let old_job = self.jobs.get(*idx).unwrap();
-            let f = &job.1.0;
-            log!(WARN, "{f}:{l1}: Overriding recipe for target: '{key}'", l1 = job.1.1);
-            log!(WARN, "{f}:{l2}: Defined here", l2 = old_job.1.1);
-            self.jobs.remove(*idx);
+            let f = &job.info.0;
+            log!(WARN, "{f}:{l1}: Overriding recipe for target: '{key}'", l1 = job.info.1);
+            log!(WARN, "{f}:{l2}: Defined here", l2 = old_job.info.1);
+            self.jobs[idx] = job;
+            return Ok(())
         }
 
-        self.jobmap.insert(key.to_owned(), self.jobs.len());
-        self.jobs.push_back(job);
+        for output in job.outputs.iter() {
+            self.jobmap.insert(output.to_owned(), self.jobs.len());
+        }
+        self.jobs.push(job);
+        Ok(())
     }
 
-    fn parse_deps_ss(info: Info, line: &str, deps: &Vec::<&str>) -> RResult::<String> {
-        for caps in DEPS_REGEX.captures_iter(&line) {
+    fn parse_deps_ss(info: Info, src: &str, line: &str, deps: &[&str]) -> RResult::<String> {
+        for caps in DEPS_REGEX.captures_iter(line) {
             let idx = caps[1].parse::<usize>().unwrap_or(0);
             if deps.get(idx).is_none() {
-                return Err(RakeError::DepsIndexOutOfBounds(info, deps.len()));
+                return Err(RakeError::DepsIndexOutOfBounds(info.at(src, &caps[0]), deps.len()));
             }
         }
 
-        let deps = DEPS_REGEX.replace_all(&line, |caps: &Captures| {
+        let deps = DEPS_REGEX.replace_all(line, |caps: &Captures| {
             let idx = caps[1].parse::<usize>().unwrap_or(0);
             deps[idx]
         }).to_string();
@@ -120,51 +243,215 @@ impl<'a> Rakefile<'a> {
         Ok(deps)
     }
 
+    // `src` is the line as it's written in the Rakefile, errors are pointed at it
     fn parse_special_symbols
     (
-        &self,
+        info: Info,
+        src: &str,
         target: &str,
         deps_joined: &str,
-        deps: &Vec::<&str>,
+        deps: &[&str],
+        stem: Option::<&str>,
         line: &str
     ) -> RResult::<String>
     {
         use SSymbol::*;
 
-        let mut line = Self::parse_deps_ss(Info::from(self), &line, &deps)?;
+        let mut line = Self::parse_deps_ss(info.to_owned(), src, line, deps)?;
+
+        if let Some(stem) = stem {
+            sreplace!(line, Stem, stem);
+        }
 
-        sreplace!(line, MakeTarget, &target);
-        sreplace!(line, RakeTarget, &target);
+        sreplace!(line, MakeTarget, target);
+        sreplace!(line, RakeTarget, target);
 
-        sreplace!(line, MakeDeps, &deps_joined);
-        sreplace!(line, RakeDeps, &deps_joined);
+        sreplace!(line, MakeDeps, deps_joined);
+        sreplace!(line, RakeDeps, deps_joined);
 
         if line.contains(&SSymbol::MakeDep.to_string())
         || line.contains(&SSymbol::RakeDep.to_string())
         {
-            let Some(first_dep) = deps.get(0) else {
-                return Err(RakeError::DepsSSwithoutDeps(Info::from(self)))
+            let Some(first_dep) = deps.first() else {
+                let ss = if line.contains(&MakeDep.to_string()) { MakeDep } else { RakeDep };
+                return Err(RakeError::DepsSSwithoutDeps(info.at(src, &ss.to_string())))
             };
-            sreplace!(line, MakeDep, &first_dep);
-            sreplace!(line, RakeDep, &first_dep);
+            sreplace!(line, MakeDep, first_dep);
+            sreplace!(line, RakeDep, first_dep);
         }
 
         Ok(line)
     }
 
+    // Whether staleness of the target is decided by content hashes, see: `-H` and `.RAKE_HASH`
+    #[inline]
+    fn is_hashed(&self, target: &str) -> bool {
+        self.hash_all || self.hashed.contains(target) || self.comptime.rcfg().is_hash()
+    }
+
+    #[inline(always)]
+    fn info_at(&self, token: &str) -> Info {
+        Info::from(self).at(&self.line, token)
+    }
+
+    #[inline]
+    fn scope_info_at(&self, scope: &Scope, token: &str) -> Info {
+        match &scope.src {
+            Some((info, line)) => info.to_owned().at(line, token),
+            None => self.info_at(token)
+        }
+    }
+
+    fn find_var(&self, name: &str) -> Option::<Cow::<'_, Var>> {
+        if let Some(var) = self.vars.get(name) {
+            return Some(Cow::Borrowed(var))
+        }
+
+        env::var(name).ok().map(|value| {
+            Cow::Owned(Var { value, flavor: Flavor::Simple, origin: Origin::Environment })
+        })
+    }
+
+    // Values of the exported variables to pass to the recipe commands
+    fn exported_env(&self) -> RResult::<Vec::<(String, String)>> {
+        self.exports.iter()
+            .filter(|(name, _)| self.vars.contains_key(*name))
+            .map(|(name, src)| {
+                let mut scope = Scope { src: Some(src.to_owned()), ..Scope::default() };
+                Ok((name.to_owned(), self.expand_vars(&format!("$({name})"), &mut scope)?))
+            })
+            .collect()
+    }
+
+    #[inline(always)]
     fn parse_vars(&self, line: &str) -> RResult::<String> {
-        for caps in VARS_REGEX.captures_iter(&line) {
-            if self.vars.get(&caps[1]).is_none() {
-                return Err(RakeError::InvalidValue(Info::from(self), caps[1].to_owned()))
+        self.expand_vars(line, &mut Scope::default())
+    }
+
+    // Returns index of the `)`, that closes the reference, taking nested ones into account
+    fn find_closing_paren(s: &str) -> Option::<usize> {
+        let mut depth = 0usize;
+        for (idx, c) in s.char_indices() {
+            match c {
+                '(' => depth += 1,
+                ')' if depth == 0 => return Some(idx),
+                ')' => depth -= 1,
+                _ => {}
+            }
+        }
+        None
+    }
+
+    fn expand_vars(&self, line: &str, scope: &mut Scope) -> RResult::<String> {
+        let mut ret = String::with_capacity(line.len());
+        let mut rest = line;
+        while let Some(start) = rest.find("$(") {
+            ret.push_str(&rest[..start]);
+
+            let inner = &rest[start + 2..];
+            let Some(end) = Self::find_closing_paren(inner) else {
+                return Err(RakeError::UnterminatedReference(self.scope_info_at(scope, &rest[start..]), rest[start..].to_owned()))
+            };
+
+            ret.push_str(&self.expand_reference(&inner[..end], scope)?);
+            rest = &inner[end + 1..];
+        }
+
+        ret.push_str(rest);
+        Ok(ret)
+    }
+
+    // Expands whatever is inside of `$(...)`: either a function call or a variable,
+    // name of which can be computed too, like: `$(cflags_$(mode))`.
+    fn expand_reference(&self, inner: &str, scope: &mut Scope) -> RResult::<String> {
+        if let Some((name, args)) = inner.split_once(char::is_whitespace) {
+            if let Some(func) = Func::from_name(name) {
+                return self.call_func(func, inner, args.trim_start(), scope)
+            }
+        }
+
+        let name = self.expand_vars(inner, scope)?;
+        let name = name.trim();
+        if let Some((_, value)) = scope.locals.iter().rev().find(|(local, _)| local == name) {
+            return Ok(value.to_owned())
+        }
+
+        let Some(var) = self.find_var(name) else {
+            // Looks like a call, like: `$(patsubts a,b,c)`, rather than a variable
+            if let Some((func, _)) = inner.trim().split_once(char::is_whitespace).filter(|(func, _)| !func.contains('$')) {
+                let suggestion = suggest(func, Func::ALL.iter().map(Func::name)).map(ToOwned::to_owned);
+                return Err(RakeError::UnknownFunction(self.scope_info_at(scope, &format!("$({inner})")), func.to_owned(), suggestion))
+            }
+
+            let names = self.vars.keys().chain(scope.locals.iter().map(|(local, _)| local));
+            let suggestion = suggest(name, names.map(String::as_str)).map(ToOwned::to_owned);
+            return Err(RakeError::InvalidValue(self.scope_info_at(scope, &format!("$({inner})")), name.to_owned(), suggestion))
+        };
+
+        match var.flavor {
+            Flavor::Simple => Ok(var.value.to_owned()),
+            Flavor::Recursive => {
+                if scope.expanding.iter().any(|x| x == name) {
+                    return Err(RakeError::RecursiveVariable(self.scope_info_at(scope, &format!("$({inner})")), name.to_owned()))
+                }
+
+                scope.expanding.push(name.to_owned());
+                let value = self.expand_vars(&var.value, scope)?;
+                scope.expanding.pop();
+                Ok(value)
             }
         }
+    }
+
+    // `inner` is the whole call, for error reporting
+    fn call_func(&self, func: Func, inner: &str, args: &str, scope: &mut Scope) -> RResult::<String> {
+        let token = format!("$({inner})");
+        let args = func.split_args(args);
+        let (min, _) = func.arity();
+        if args.len() < min {
+            let err = format!("`{name}` expects at least {min} arguments, got: {n}", name = func.name(), n = args.len());
+            return Err(RakeError::InvalidFunctionCall(self.scope_info_at(scope, &token), err))
+        }
+
+        match func {
+            // `$(foreach var,list,text)`, `text` is expanded for every word of the `list`
+            Func::Foreach => {
+                let name = self.expand_vars(&args[0], scope)?.trim().to_owned();
+                let list = self.expand_vars(&args[1], scope)?;
+                let mut ret = Vec::new();
+                for word in list.split_whitespace() {
+                    scope.locals.push((name.to_owned(), word.to_owned()));
+                    let text = self.expand_vars(&args[2], scope);
+                    scope.locals.pop();
+                    ret.push(text?);
+                }
+                Ok(ret.join(" "))
+            }
+            // `$(if cond,then[,else])`, only the taken branch is expanded
+            Func::If => if !self.expand_vars(&args[0], scope)?.trim().is_empty() {
+                self.expand_vars(&args[1], scope)
+            } else if let Some(otherwise) = args.get(2) {
+                self.expand_vars(otherwise, scope)
+            } else {
+                Ok(String::new())
+            }
+            Func::Shell => {
+                let cmd = self.expand_vars(&args[0], scope)?;
+                shell(&cmd).map_err(|err| RakeError::ShellFailed(self.scope_info_at(scope, &token), cmd.trim().to_owned(), err))
+            }
+            _ => {
+                let args = args.iter()
+                    .map(|arg| self.expand_vars(arg, scope))
+                    .collect::<RResult::<Vec::<_>>>()?;
 
-        Ok(VARS_REGEX.replace_all(&line, |caps: &Captures| self.vars.get(&caps[1]).unwrap()).to_string())
+                func.apply(&args).map_err(|err| RakeError::InvalidFunctionCall(self.scope_info_at(scope, &token), err))
+            }
+        }
     }
 
     #[inline(always)]
     fn find_job_by_target_mut(&mut self, target: &str) -> Option::<&mut RJob> {
-        self.jobs.iter_mut().find(|j| j.0.target().eq(target))
+        self.jobmap.get(target).map(|idx| &mut self.jobs[*idx])
     }
 
     #[inline(always)]
@@ -173,14 +460,55 @@ impl<'a> Rakefile<'a> {
         self.iter.next();
     }
 
+    // Rest of the body of the failed job is skipped, so that its lines are not parsed as the top level ones
     fn parse_job(&mut self, line: &str) -> RResult::<()> {
-        let line = self.parse_vars(&line)?;
-        let new_idx = line.chars().position(|x| x.eq(&':')).unwrap();
+        let ret = self.parse_job_inner(line);
+        if ret.is_err() { self.skip_job_body() }
+        ret
+    }
+
+    fn parse_job_inner(&mut self, src: &str) -> RResult::<()> {
+        let line = self.parse_vars(src)?;
+
+        // The `:` can be gone after the expansion, like in: `a$(subst :,,b:c)`
+        let Some(new_idx) = line.find(':') else {
+            return Err(RakeError::UnrecognisedLine(self.info_at(src.trim()), src.trim().to_owned()))
+        };
         let (target_untrimmed, deps_untrimmed) = line.split_at(new_idx);
+
+        // Grouped targets, like: `a.tab.c a.tab.h &: a.y`, are all produced by a single execution of the recipe,
+        // otherwise each of the targets gets its own instance of it, like in: `a b: dep`.
+        let (target_untrimmed, grouped) = match target_untrimmed.strip_suffix('&') {
+            Some(target_untrimmed) => (target_untrimmed, true),
+            None => (target_untrimmed, false)
+        };
         let target = target_untrimmed.trim();
+        let targets = target.split_whitespace().collect::<Vec::<_>>();
 
         if target.is_empty() {
-            return Err(RakeError::NoTarget(Info::from(&*self)))
+            return Err(RakeError::NoTarget(self.info_at(":")))
+        }
+
+        // Depfile declaration, like: `build/%.o: depfile = $t.d`. The value is expanded
+        // along with the rest of the line, so `=` and `:=` are the same here.
+        let rest = &deps_untrimmed[1..];
+        if let Some((op, start, end)) = AssignOp::find(rest).filter(|(_, start, _)| rest[..*start].trim() == Self::DEPFILE_VAR) {
+            if op == AssignOp::Append {
+                let op = &rest[start..end];
+                return Err(RakeError::InvalidDepfileOperator(self.info_at(op), op.to_owned()))
+            }
+
+            let depfile = Depfile {
+                path: rest[end..].trim().to_owned(),
+                info: Info::from(&*self),
+                src: self.line.to_owned()
+            };
+            for target in targets {
+                if op == AssignOp::Conditional && self.depfiles.contains_key(target) { continue }
+                self.depfiles.insert(target.to_owned(), depfile.to_owned());
+            }
+            self.row += 1;
+            return Ok(())
         }
 
         let deps = deps_untrimmed
@@ -191,203 +519,536 @@ impl<'a> Rakefile<'a> {
         let deps_joined = deps.join(" ");
         let signature_row = self.row;
 
-        let mut body = Vec::new();
+        // Targets, that `$t` is bound to in the recipes, grouped ones share a single recipe bound to the first of them
+        let bound = if grouped { &targets[..1] } else { &targets[..] };
+        let mut bodies = vec![Vec::new(); bound.len()];
+        let mut sources = vec![Vec::new(); bound.len()];
+
+        // Rows of the body lines start right after the signature
+        self.row += 1;
+
         while let Some(next_line) = self.iter.peek() {
             let line = next_line.to_owned();
+            let line = line.as_str();
+            self.line.clone_from(next_line);
             if line.starts_with('#') {
                 self.advance();
                 continue
             }
 
-            let line = self.parse_special_symbols(&target, &deps_joined, &deps, &line)?;
-            let line = self.parse_vars(&line)?;
-
-            let trimmed = line.trim().to_owned();
+            // Conditional directives can be used inside of the job body too,
+            // unless they are indented, then they are just recipe lines.
+            if let Some(directive) = Directive::parse(line).filter(|_| !line.starts_with(char::is_whitespace)) {
+                self.parse_conditional(directive)?;
+                self.advance();
+                continue
+            }
 
-            // Allow people to use both tabs and spaces
-            if line.starts_with('\t') {
-                body.push(trimmed);
+            if !self.is_active() {
                 self.advance();
                 continue
             }
 
+            // Allow people to use both tabs and spaces
             let whitespace_count = line.chars().take_while(|c| c.is_whitespace()).count();
             match whitespace_count {
-                Self::TAB_WIDTH => {
-                    self.advance();
-                    body.push(trimmed)
+                _ if line.starts_with('\t') => {}
+                Self::TAB_WIDTH => {}
+                i @ 1.. if !line.trim().is_empty() => {
+                    let end = line.len() - line.trim_start().len();
+                    return Err(RakeError::InvalidIndentation(Info::from(&*self).span(line, 0, end), i))
                 }
-                i @ 1.. => return Err(RakeError::InvalidIndentation(Info::from(&*self), i)),
-                _ => if trimmed.is_empty() { self.advance(); } else { self.row += 1; break }
+                _ => if line.trim().is_empty() { self.advance(); continue } else { break }
             };
+
+            let src = line;
+            for ((target, body), sources) in bound.iter().zip(bodies.iter_mut()).zip(sources.iter_mut()) {
+                let is_pattern = target.contains('%');
+                let line = if is_pattern {
+                    src.to_owned()
+                } else {
+                    Self::parse_special_symbols(Info::from(&*self), src, target, &deps_joined, &deps, None, src)?
+                };
+                let line = self.parse_vars(&line)?;
+
+                let trimmed = line.trim();
+                if !trimmed.is_empty() {
+                    body.push(trimmed.to_owned());
+                    if is_pattern { sources.push((self.row, src.to_owned())) }
+                }
+            }
+            self.advance();
         }
 
-        let cfg = self.comptime.cfg();
-        let cmd = body.iter().fold(RobCommand::from(cfg.to_owned()), |mut cmd, line| {
-            cmd.append_mv(&[line]);
-            cmd
-        });
+        let ss_check1 = [SSymbol::MakePhony, SSymbol::RakePhony].iter().any(|ss| ss.to_string().eq(target));
+        let ss_check2 = parse_special_job_by_target!(self, target, deps, echo, false, SSymbol::MakeSilent);
+        let ss_check3 = SSymbol::RakeHash.to_string().eq(target);
+        let ss_check4 = SSymbol::RakeDepfile.to_string().eq(target);
+        if ss_check1 {
+            self.phony.extend(deps.iter().map(|d| d.to_string()));
+        }
+
+        if ss_check3 {
+            self.hash_all |= deps.is_empty();
+            self.hashed.extend(deps.iter().map(|d| d.to_string()));
+        }
 
-        let ss_check1 = parse_special_job_by_target!(self, target, deps, cmd, phony, true, SSymbol::MakePhony, SSymbol::RakePhony);
-        let ss_check2 = parse_special_job_by_target!(self, target, deps, cmd, echo, false, SSymbol::MakeSilent);
-        if !(ss_check1 && ss_check2) {
-            let job = Job::new(target, deps, cmd);
+        if ss_check4 {
+            let path = format!("{t}.d", t = SSymbol::RakeTarget);
+            let depfile = Depfile { src: path.to_owned(), path, info: Info::from((&*self, signature_row)) };
+            if deps.is_empty() {
+                self.depfiles.insert("%".to_owned(), depfile);
+            } else {
+                self.depfiles.extend(deps.iter().map(|d| (d.to_string(), depfile.to_owned())));
+            }
+        }
+
+        if !(ss_check1 || ss_check2 || ss_check3 || ss_check4) {
+            let cfg = self.comptime.cfg().to_owned();
             let info = Info::from((&*self, signature_row));
-            let rjob = RJob(job, info);
-            self.append_job(rjob);
+            for (idx, (body, sources)) in bodies.into_iter().zip(sources).enumerate() {
+                let outputs = if grouped { &targets[..] } else { &targets[idx..=idx] };
+                let mut rjob = RJob::new(outputs, deps.to_owned(), body, cfg.to_owned(), info.to_owned());
+                rjob.sources = sources;
+                if rjob.target.contains('%') {
+                    self.patterns.push(rjob);
+                } else {
+                    self.append_job(rjob)?;
+                }
+            }
         }
 
         Ok(())
     }
 
-    fn handle_output
-    (
-        &self,
-        dep_job_info: Info,
-        output: RobResult::<Vec::<Output>>
-    ) -> RResult::<()>
-    {
-        let keepgoing = self.comptime.cfg().keepgoing;
-        match output {
-            Ok(ok) => if let Some(err) = ok.into_iter().find(|out| matches!(out.status.code(), Some(code) if code != 0)) {
-                // Error-message printing handled in robuild: https://github.com/rakivo/robuild
-                if !keepgoing {
-                    let err = String::from_utf8_lossy(&err.stderr);
-                    Err(RakeError::FailedToExecute(dep_job_info, err.to_string()))
-                } else { Ok(()) }
-            } else { Ok(()) }
-            Err(err) => match err {
-                RobError::NotFound(file_path) => Err(RakeError::InvalidDependency(dep_job_info, file_path)),
-                _ => Err(RakeError::FailedToExecute(dep_job_info, err.to_string()))
+    // The file can be made, if it's either a target of an explicit job,
+    // an existing file, or it can be made by a pattern rule, which is not used in the chain yet.
+    fn can_make(&self, name: &str, chain: &mut Vec::<usize>) -> bool {
+        self.jobmap.contains_key(name)
+            || Rob::path_exists(name)
+            || self.find_pattern(name, chain).is_some()
+    }
+
+    // Pattern rule applies only if all of its deps can be made, out of the
+    // applicable rules the one with the shortest stem wins.
+    fn find_pattern<'s>(&self, name: &'s str, chain: &mut Vec::<usize>) -> Option::<(usize, &'s str)> {
+        let mut best: Option::<(usize, &str)> = None;
+        for (idx, pattern) in self.patterns.iter().enumerate() {
+            if chain.contains(&idx) { continue }
+
+            let Some(stem) = pattern.outputs.iter().find_map(|output| match_stem(output, name)) else { continue };
+            if matches!(best, Some((_, best_stem)) if best_stem.len() <= stem.len()) { continue }
+
+            chain.push(idx);
+            let applies = pattern.deps.iter().all(|dep| self.can_make(&dep.replacen('%', stem, 1), chain));
+            chain.pop();
+
+            if applies { best = Some((idx, stem)) }
+        }
+        best
+    }
+
+    // Grouped pattern rule, like: `%.tab.c %.tab.h &: %.y`, is instantiated with all of its outputs at once
+    fn instantiate_pattern(&self, idx: usize, stem: &str) -> RResult::<RJob> {
+        let pattern = &self.patterns[idx];
+        let outputs = pattern.outputs.iter().map(|output| output.replacen('%', stem, 1)).collect::<Vec::<_>>();
+        let outputs = outputs.iter().map(String::as_str).collect::<Vec::<_>>();
+        let deps = pattern.deps.iter().map(|dep| dep.replacen('%', stem, 1)).collect::<Vec::<_>>();
+        let deps = deps.iter().map(String::as_str).collect::<Vec::<_>>();
+        let deps_joined = deps.join(" ");
+
+        let body = pattern.body.iter().zip(pattern.sources.iter()).map(|(line, (row, src))| {
+            let info = Info(pattern.info.0.to_owned(), *row, None);
+            Self::parse_special_symbols(info, src, outputs[0], &deps_joined, &deps, Some(stem), line)
+        }).collect::<RResult::<Vec::<_>>>()?;
+
+        Ok(RJob::new(&outputs, deps, body, pattern.cfg.to_owned(), pattern.info.to_owned()))
+    }
+
+    // Instantiate pattern rules for the deps and goals, that have no explicit job.
+    fn resolve_patterns(&mut self) -> RResult::<()> {
+        if self.patterns.is_empty() { return Ok(()) }
+
+        let mut names = self.jobs.iter()
+            .flat_map(|job| job.deps.iter().cloned())
+            .chain(self.comptime.potential_jobs().iter().cloned())
+            .collect::<Vec::<_>>();
+
+        while let Some(name) = names.pop() {
+            if self.jobmap.contains_key(&name) { continue }
+
+            let Some((idx, stem)) = self.find_pattern(&name, &mut Vec::new()) else { continue };
+            let job = self.instantiate_pattern(idx, stem)?;
+            names.extend(job.deps.iter().cloned());
+            self.append_job(job)?;
+        }
+
+        Ok(())
+    }
+
+    // Explicit declaration for the target wins, out of the
+    // matching patterns the one with the shortest stem wins.
+    fn find_depfile<'s>(&'s self, target: &'s str) -> Option::<(&'s Depfile, Option::<&'s str>)> {
+        if let Some(depfile) = self.depfiles.get(target) {
+            return Some((depfile, None))
+        }
+
+        self.depfiles.iter()
+            .filter_map(|(pattern, depfile)| match_stem(pattern, target).map(|stem| (depfile, Some(stem))))
+            .min_by_key(|(_, stem)| stem.map(str::len))
+    }
+
+    // Expands declared depfiles of the jobs, like: `$t.d` into `build/foo.o.d`
+    fn resolve_depfiles(&mut self) -> RResult::<()> {
+        if self.depfiles.is_empty() { return Ok(()) }
+
+        for idx in 0..self.jobs.len() {
+            let job = &self.jobs[idx];
+            if self.phony.contains(&job.target) { continue }
+
+            let Some((depfile, stem)) = self.find_depfile(&job.target) else { continue };
+            let deps = job.deps.iter().map(String::as_str).collect::<Vec::<_>>();
+            let Depfile { path, info, src } = depfile;
+            let depfile = Self::parse_special_symbols(info.to_owned(), src, &job.target, &deps.join(" "), &deps, stem, path)?;
+            self.jobs[idx].depfile = Some(depfile);
+        }
+
+        Ok(())
+    }
+
+    // All of the goals are scheduled at once, so jobs they share are executed only once.
+    // Goals are paired with the names they were requested by, since grouped targets share a single node
+    fn execute_goals(&self, goals: &[(NodeId, &str)]) -> RResult::<()> {
+        let ids = goals.iter().map(|(id, _)| *id).collect::<Vec::<_>>();
+        let executed = Sched::new(self, &ids)?.run()?;
+
+        // `-q` reports only through the exit code
+        if self.comptime.rcfg().mode() == Mode::Question {
+            self.comptime.handle_ucd();
+            exit(i32::from(executed.contains(&true)))
+        }
+
+        for ((_, target), executed) in goals.iter().zip(executed) {
+            if !executed {
+                log!(INFO, "Nothing to be done for '{target}'.");
             }
         }
+        Ok(())
     }
 
-    // Find a way to do that without cloning each job if it's even possible.
-    fn job_as_dep_check(&mut self, job: RJob) -> RResult<&mut Self> {
-        let mut stack = vec![job];
+    fn parse_variable_declaration(&mut self, op: AssignOp, start: usize, end: usize, line: &str) -> RResult::<()> {
+        let name = line[..start].trim();
+        let value = line[end..].trim();
 
-        while let Some(mut curr_job) = stack.pop() {
-            if curr_job.0.deps().iter().any(|d| Rob::is_file(&d)) {
-                let out = curr_job.0.execute_async_dont_exit();
-                self.handle_output(curr_job.1, out)?;
-            } else {
-                for dep in curr_job.0.deps().iter() {
-                    if let Some(dep_job) = self.find_job_by_target_mut(&dep.to_owned()) {
-                        stack.push(dep_job.to_owned());
-                    } else if !(Rob::is_file(&dep) || Rob::is_dir(&dep)) {
-                        return Err(RakeError::InvalidDependency(curr_job.1, dep.to_owned()));
+        if name.split_whitespace().count() > 1 {
+            return Err(RakeError::MultipleNames(self.info_at(name)))
+        }
+
+        if matches!(self.vars.get(name), Some(var) if var.origin == Origin::CommandLine) {
+            self.row += 1;
+            return Ok(())
+        }
+
+        let var = match (op, self.find_var(name)) {
+            (AssignOp::Conditional, Some(..)) => None,
+            (AssignOp::Append, Some(var)) => {
+                let appended = match var.flavor {
+                    Flavor::Simple => self.parse_vars(value)?,
+                    Flavor::Recursive => value.to_owned()
+                };
+                let value = if var.value.is_empty() {
+                    appended
+                } else {
+                    format!("{old} {appended}", old = var.value)
+                };
+                Some(Var { value, flavor: var.flavor, origin: Origin::File })
+            }
+            (AssignOp::Simple, _) => {
+                let value = match self.parse_vars(value) {
+                    Ok(value) => value,
+                    Err(err) => {
+                        // Define it anyway, so that its uses don't add "Undefined variable" errors on top of this one
+                        self.vars.insert(name.to_owned(), Var { value: String::new(), flavor: Flavor::Simple, origin: Origin::File });
+                        return Err(err)
                     }
-                }
+                };
+                Some(Var { value, flavor: Flavor::Simple, origin: Origin::File })
             }
+            _ => Some(Var { value: value.to_owned(), flavor: Flavor::Recursive, origin: Origin::File })
+        };
+
+        if let Some(var) = var {
+            self.vars.insert(name.to_owned(), var);
         }
+        self.row += 1;
 
-        Ok(self)
+        Ok(())
     }
 
-    fn execute_job(&mut self, mut job: RJob) -> RResult::<()> {
-        let keepgoing = self.comptime.cfg().keepgoing;
-        self.job_as_dep_check(job.to_owned())?;
-        if let Err(err) = job.0.execute_async_dont_exit_unchecked() {
-            // Error-message printing handled in robuild: https://github.com/rakivo/robuild
-            if !keepgoing {
-                Err(RakeError::FailedToExecute(job.1.to_owned(), err.to_string()))
-            } else {
-                Ok(())
-            }
+    // `export NAME ...` or `export NAME = value`
+    fn parse_export(&mut self, line: &str) -> RResult::<()> {
+        if let Some((op, start, end)) = AssignOp::find(line) {
+            let src = (Info::from(&*self), self.line.to_owned());
+            self.exports.insert(line[..start].trim().to_owned(), src);
+            self.parse_variable_declaration(op, start, end, line)
         } else {
+            let names = self.parse_vars(line)?;
+            let src = (Info::from(&*self), self.line.to_owned());
+            self.exports.extend(names.split_whitespace().map(|name| (name.to_owned(), src.to_owned())));
+            self.row += 1;
             Ok(())
         }
     }
 
-    fn parse_variable_declaration(&mut self, idx: usize, line: &'a str) -> RResult::<()> {
-        let (name_untrimmed, value_untrimmed) = line.split_at(idx);
-        let name = name_untrimmed.trim();
+    // `include FILE ...` or `-include FILE ...`, included files share variables and jobs
+    // with the including one, but keep their own paths and rows for error reporting.
+    fn parse_include(&mut self, files: &str, optional: bool) -> RResult::<()> {
+        let files = self.parse_vars(files)?;
+        self.row += 1;
 
-        if name.split_whitespace().count() > 1 {
-            return Err(RakeError::MultipleNames(Info::from(&*self)))
+        for file in files.split_whitespace() {
+            let info = Info::from((&*self, self.row - 1)).at(&self.line, file);
+            let file_path = PathBuf::from(file);
+            let Ok(file_str) = read_to_string(&file_path) else {
+                if optional { continue }
+                return Err(RakeError::IncludeNotFound(info, file.to_owned()))
+            };
+
+            let canonical = file_path.canonicalize().unwrap_or_else(|_| file_path.to_owned());
+            if self.including.contains(&canonical) {
+                return Err(RakeError::RecursiveInclude(info, file.to_owned()))
+            }
+
+            self.including.push(canonical);
+            let file_path = mem::replace(&mut self.file_path, file_path);
+            let row = mem::replace(&mut self.row, 1);
+            let iter = mem::replace(&mut self.iter, Self::lines(&file_str));
+            let line = mem::take(&mut self.line);
+
+            self.parse();
+
+            self.line = line;
+            self.iter = iter;
+            self.row = row;
+            self.file_path = file_path;
+            self.including.pop();
+        }
+
+        Ok(())
+    }
+
+    #[inline(always)]
+    fn is_active(&self) -> bool {
+        self.conds.iter().all(|cond| cond.active)
+    }
+
+    fn eval_condition(&self, directive: Directive) -> RResult::<bool> {
+        use Directive::*;
+        match directive {
+            Ifeq(args) | Ifneq(args) => {
+                let Some((lhs, rhs)) = Directive::split_args(args) else {
+                    return Err(RakeError::InvalidConditional(self.info_at(args), directive.keyword().to_owned()))
+                };
+                let eq = self.parse_vars(lhs)?.trim() == self.parse_vars(rhs)?.trim();
+                Ok(eq == matches!(directive, Ifeq(..)))
+            }
+            Ifdef(name) | Ifndef(name) => {
+                let name = self.parse_vars(name)?;
+                let defined = self.find_var(name.trim()).is_some_and(|var| !var.value.is_empty());
+                Ok(defined == matches!(directive, Ifdef(..)))
+            }
+            Else(..) | Endif => unreachable!()
         }
+    }
 
-        let value_trimmed = value_untrimmed[2..].trim();
+    fn parse_conditional(&mut self, directive: Directive) -> RResult::<()> {
+        use Directive::*;
 
-        let value = if value_trimmed.starts_with("$(") && value_trimmed.ends_with(')') {
-            match self.vars.get(&value_trimmed[2..value_trimmed.len() - 1]) {
-                Some(val) => val,
-                _ => return Err(RakeError::InvalidValue(Info::from(&*self), value_trimmed.to_owned()))
+        let info = self.info_at(directive.keyword());
+        match directive {
+            Endif => if self.conds.pop().is_none() {
+                return Err(RakeError::UnmatchedDirective(info, directive.keyword().to_owned()))
             }
-        } else {
-            value_trimmed
-        };
+            Else(rest) => {
+                let parent_active = self.conds.iter().rev().skip(1).all(|cond| cond.active);
+                let Some(cond) = self.conds.last() else {
+                    return Err(RakeError::UnmatchedDirective(info, directive.keyword().to_owned()))
+                };
+
+                if cond.in_else {
+                    return Err(RakeError::InvalidConditional(info, "else after else".to_owned()))
+                }
 
-        self.vars.insert(name, value);
-        self.row += 1;
+                let (active, in_else) = match Directive::parse(rest) {
+                    _ if cond.taken || !parent_active => (false, rest.is_empty()),
+                    None if rest.is_empty() => (true, true),
+                    Some(next @ (Ifeq(..) | Ifneq(..) | Ifdef(..) | Ifndef(..))) => (self.eval_condition(next)?, false),
+                    _ => return Err(RakeError::InvalidConditional(self.info_at(rest), format!("else {rest}")))
+                };
+
+                let cond = self.conds.last_mut().unwrap();
+                cond.active = active;
+                cond.taken |= active;
+                cond.in_else = in_else;
+            }
+            _ => {
+                // Don't evaluate conditions inside of the branches that are not taken.
+                // Invalid conditional still opens a block, so that its `endif` is matched.
+                let active = if self.is_active() {
+                    match self.eval_condition(directive) {
+                        Ok(active) => active,
+                        Err(err) => {
+                            self.conds.push(Cond { active: false, taken: true, in_else: false, info });
+                            return Err(err)
+                        }
+                    }
+                } else { false };
+                let taken = active || !self.is_active();
+                self.conds.push(Cond { active, taken, in_else: false, info });
+            }
+        }
 
         Ok(())
     }
 
-    fn parse_line(&mut self, line: &'a str) -> RResult::<()> {
-        if line.trim().is_empty() || line.starts_with('#') {
+    fn parse_line(&mut self, line: &str) -> RResult::<()> {
+        if let Some(directive) = Directive::parse(line) {
+            self.parse_conditional(directive)?;
+            self.row += 1;
+        } else if line.trim().is_empty() || line.starts_with('#') || !self.is_active() {
             self.row += 1;
-        } else if line.chars().find(|x| x.eq(&':')).is_some() {
+        } else if let Some(rest) = line.strip_prefix("include").filter(|rest| rest.starts_with(char::is_whitespace)) {
+            self.parse_include(rest, false)?;
+        } else if let Some(rest) = line.strip_prefix("-include").filter(|rest| rest.starts_with(char::is_whitespace)) {
+            self.parse_include(rest, true)?;
+        } else if let Some(rest) = line.strip_prefix("export").filter(|rest| rest.starts_with(char::is_whitespace)) {
+            self.parse_export(rest)?;
+        } else if let Some((op, start, end)) = AssignOp::find(line) {
+            self.parse_variable_declaration(op, start, end, line)?;
+        } else if line.contains(':') {
             self.parse_job(line)?;
-        } else if let Some(eq_idx) = line.chars().position(|x| x.eq(&'=')) {
-            self.parse_variable_declaration(eq_idx, line)?;
-        } else if !line.trim().is_empty() {
-            panic!("Wtf is dis scheisse: `{line}` ??? ");
+        } else {
+            return Err(RakeError::UnrecognisedLine(self.info_at(line.trim()), line.trim().to_owned()))
         }
         Ok(())
     }
 
-    fn check_potential_jobs(&mut self) -> RResult::<Vec<RJob>> {
-        let ret = self.comptime.potential_jobs().iter().try_fold(HashSet::new(), |mut set, pj| {
-            if let Some(idx) = self.jobs.iter().position(|j| j.0.target().eq(pj)) {
-                set.insert(idx);
-                Ok(set)
+    fn check_potential_jobs(&self) -> RResult::<Vec<(NodeId, &str)>> {
+        let ret = self.comptime.potential_jobs().iter().try_fold(Vec::new(), |mut goals, pj| {
+            if let Some(id) = self.graph.id(pj).filter(|id| matches!(self.graph.nodes[*id].kind, NodeKind::Job(..))) {
+                if !goals.iter().any(|(goal, _)| *goal == id) { goals.push((id, pj.as_str())); }
+                Ok(goals)
             } else {
                 let names = self.jobs.iter()
-                    .filter_map(|j| {
-                        let tar = j.0.target().to_owned();
+                    .flat_map(|j| j.outputs.iter())
+                    .filter_map(|tar| {
+                        let tar = tar.to_owned();
                         match SSymbol::try_from(&tar) {
                             Ok(..) => None,
                             Err(..) => Some(tar)
                         }
-                    }).collect::<Vec::<_>>().join(", ");
+                    }).collect::<Vec::<_>>();
 
-                Err(RakeError::InvalidArgument(pj.to_owned(), names))
+                let suggestion = suggest(pj, names.iter().map(String::as_str)).map(ToOwned::to_owned);
+
+                Err(RakeError::InvalidArgument(pj.to_owned(), names.join(", "), suggestion))
             }
-        })?.into_iter().map(|idx| self.jobs[idx].to_owned()).collect();
+        })?;
 
         Ok(ret)
     }
 
-    fn execute_jobs(&mut self) {
+    fn execute_jobs(&self) {
         let pot_jobs = self.check_potential_jobs().unwrap_or_report();
 
-        let jobs = if !pot_jobs.is_empty() {
+        let goals = if !pot_jobs.is_empty() {
             pot_jobs
+        } else if let Some(first) = self.jobs.first() {
+            vec![(self.graph.id(&first.target).unwrap(), first.target.as_str())]
         } else {
-            vec![self.jobs[0].to_owned()]
+            return
         };
 
-        jobs.into_iter().for_each(|j| self.execute_job(j).unwrap_or_report());
+        self.execute_goals(&goals).unwrap_or_report();
+    }
+
+    // Consumes the rest of the job body by the same rules as the body loop of `parse_job`,
+    // so conditional directives inside of the body are still tracked.
+    fn skip_job_body(&mut self) {
+        while let Some(next_line) = self.iter.peek() {
+            let line = next_line.to_owned();
+            self.line.clone_from(&line);
+            if let Some(directive) = Directive::parse(&line).filter(|_| !line.starts_with(char::is_whitespace)) {
+                if let Err(err) = self.parse_conditional(directive) {
+                    self.errors.push(err);
+                }
+            } else if !(line.starts_with('#') || line.starts_with(char::is_whitespace) || line.trim().is_empty() || !self.is_active()) {
+                break
+            }
+            self.advance();
+        }
+    }
+
+    // Errors don't stop parsing, they are collected, so that all of them are reported at once
+    fn parse(&mut self) {
+        let depth = self.conds.len();
+        while let Some(line) = self.iter.next() {
+            let (row, left, errors) = (self.row, self.iter.len(), self.errors.len());
+            self.line.clone_from(&line);
+            let Err(err) = self.parse_line(&line) else { continue };
+
+            // Before the errors of the lines skipped after it, if any
+            self.errors.insert(errors, err);
+
+            // Line that failed may have been left partially consumed, so continue right after it,
+            // skipping indented lines, that can't be parsed on their own. Bodies of jobs are skipped by `parse_job`.
+            self.row = row + 1 + left - self.iter.len();
+            while self.iter.peek().is_some_and(|line| line.starts_with(char::is_whitespace) && !line.trim().is_empty()) {
+                self.advance();
+            }
+        }
+
+        // Conditionals must be closed in the same file they were opened in
+        while self.conds.len() > depth {
+            let cond = self.conds.pop().unwrap();
+            self.errors.push(RakeError::UnterminatedConditional(cond.info));
+        }
+    }
+
+    fn check_errors(&mut self) -> RResult::<()> {
+        let mut errors = mem::take(&mut self.errors);
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.pop().unwrap()),
+            _ => Err(RakeError::Multiple(errors))
+        }
     }
 
     fn init() {
-        let comptime = Comptime::new().unwrap_or_report();
+        let mut comptime = Comptime::new().unwrap_or_report();
 
-        let file_path = Self::find_rakefile().unwrap_or_report();
-        let file_str = read_to_string(&file_path).unwrap_or_report();
+        let (file_path, file_str) = Self::read_rakefile(&mut comptime).unwrap_or_report();
+
+        let vars = comptime.overrides().iter().map(|(name, value)| {
+            let var = Var { value: value.to_owned(), flavor: Flavor::Recursive, origin: Origin::CommandLine };
+            (name.to_owned(), var)
+        }).collect();
 
         let mut rakefile = Rakefile {
             comptime,
             file_path,
-            iter: file_str.lines().peekable(),
+            vars,
+            iter: Self::lines(&file_str),
             ..Self::default()
         };
 
-        while let Some(line) = rakefile.iter.next() {
-            rakefile.parse_line(&line).unwrap_or_report();
-        }
+        rakefile.including.push(rakefile.file_path.canonicalize().unwrap_or_else(|_| rakefile.file_path.to_owned()));
+        rakefile.parse();
+        rakefile.check_errors().unwrap_or_report();
+
+        rakefile.resolve_patterns().unwrap_or_report();
+        rakefile.resolve_depfiles().unwrap_or_report();
+        rakefile.graph = Graph::new(&rakefile).unwrap_or_report();
 
         rakefile.execute_jobs();
         rakefile.comptime.handle_ucd();
@@ -399,12 +1060,7 @@ fn main() {
 }
 
 /* TODO:
-    4. Async mode | Sync mode,
-    5. Variables and :=, ?=, += syntax.
-    6. @ Syntax to disable echo for specific line.
-    7. % syntax for pattern matching.
     9. Make it possible to declare dependencies of the special .PHONY, .SILENT, ... jobs, before declaration of the specified job if ykwim
     11. Factor out `MakePhony`, `RakePhony`, `MakeSilent` ..., to separate enum, because they're not special symbols
     12. Fix a shit ton of fucking bugs. It's so fucking annoying to realize that your program is useless shit after working on it for two weeks
-    13. Make `Job::execute_all_async` execute async for real, and do not wait for every fucking child every fucking job, useless piece of shit.
  */
@@ -0,0 +1,352 @@
+use std::{
+    fs::{self, OpenOptions},
+    thread,
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::mpsc,
+    time::SystemTime,
+    collections::VecDeque
+};
+use robuild::*;
+
+use crate::{
+    parse_depfile,
+    Db,
+    Entry,
+    Info,
+    Mode,
+    RJob,
+    NodeId,
+    RResult,
+    NodeKind,
+    Rakefile,
+    RakeError
+};
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    // Not needed to build the goal
+    Unreached,
+
+    Waiting,
+    Running,
+    Failed,
+
+    // Whether the job has been executed or was up to date.
+    Done(bool),
+
+    // Executed in the hash mode, but the content of the target came out the same,
+    // so the dependents don't have to be rebuilt because of it, like ninja's `restat`.
+    // Modification time of the target is set back, so it holds for dependents decided by mtimes too.
+    Unchanged
+}
+
+// DAG scheduler: walks the build graph, runs independent jobs concurrently, up to `-j N` at a time,
+// starting a job only when all of its dep jobs have finished successfully.
+pub struct Sched<'a> {
+    rake: &'a Rakefile,
+    goals: &'a [NodeId],
+
+    // Per-run memo of the job results: every node is executed at most once,
+    // and its result is shared by all of its dependents.
+    states: Vec::<State>,
+
+    // Nodes with no pending deps, in the order they were reached from the goals
+    ready: VecDeque::<NodeId>,
+
+    // Number of deps of the node that are not done yet
+    pending: Vec::<usize>,
+
+    jobs_n: usize,
+    mode: Mode,
+    keepgoing: bool,
+
+    // `-B`: every job is stale
+    always_make: bool,
+
+    db: Db
+}
+
+impl<'a> Sched<'a> {
+    pub fn new(rake: &'a Rakefile, goals: &'a [NodeId]) -> RResult::<Self> {
+        let mode = rake.comptime.rcfg().mode();
+
+        // Only actual execution runs jobs concurrently, so that the output of the other modes is in order
+        let jobs_n = match mode {
+            Mode::Execute => rake.comptime.rcfg().if_jobs()
+                .or_else(|| thread::available_parallelism().ok())
+                .map_or(1, |n| n.get()),
+            _ => 1
+        };
+        let always_make = rake.comptime.rcfg().is_always_make();
+        let keepgoing = rake.comptime.cfg().keepgoing;
+
+        let nodes = &rake.graph.nodes;
+        let mut sched = Self {
+            rake,
+            goals,
+            states: vec![State::Unreached; nodes.len()],
+            ready: VecDeque::new(),
+            pending: nodes.iter().map(|node| node.deps.len()).collect(),
+            jobs_n,
+            mode,
+            keepgoing,
+            always_make,
+            db: Db::load(rake.file_path.parent().unwrap_or(Path::new("")))
+        };
+
+        let mut stack = goals.iter().rev().copied().collect::<Vec::<_>>();
+        while let Some(id) = stack.pop() {
+            if sched.states[id] != State::Unreached { continue }
+
+            sched.states[id] = State::Waiting;
+            if sched.pending[id] == 0 {
+                sched.ready.push_back(id);
+            }
+
+            for dep in nodes[id].deps.iter().rev() {
+                if nodes[*dep].kind == NodeKind::File && !Rob::path_exists(&nodes[*dep].name) {
+                    let info = sched.info(id);
+                    return Err(RakeError::InvalidDependency(info, nodes[*dep].name.to_owned()))
+                }
+                stack.push(*dep);
+            }
+        }
+
+        Ok(sched)
+    }
+
+    fn info(&self, id: NodeId) -> Info {
+        match self.rake.graph.nodes[id].kind {
+            NodeKind::Job(idx) => self.rake.jobs[idx].info.to_owned(),
+            NodeKind::File => Info::from(self.rake)
+        }
+    }
+
+    #[inline(always)]
+    fn mtime(path: &str) -> Option::<SystemTime> {
+        Rob::get_last_modification_time(path).ok()
+    }
+
+    // Modification time of the oldest output of the job, `None` if any of them doesn't exist,
+    // since `None` is less than any `Some`.
+    #[inline]
+    fn outputs_mtime(job: &RJob) -> Option::<SystemTime> {
+        job.outputs.iter().map(|output| Self::mtime(output)).min().flatten()
+    }
+
+    // Content hash of the output, grouped outputs are hashed all together
+    fn hash_outputs(job: &RJob) -> Option::<u64> {
+        match job.outputs.as_slice() {
+            [target] => Db::hash_file(target),
+            outputs => Some(Db::hash_inputs(outputs.iter().map(String::as_str)))
+        }
+    }
+
+    // Deps discovered from the depfile of the job, when it was built last time
+    fn discovered(&self, target: &str) -> &[String] {
+        self.db.get(target).map_or(&[], |entry| &entry.deps)
+    }
+
+    #[inline]
+    fn hash_inputs(job: &RJob, discovered: &[String]) -> u64 {
+        Db::hash_inputs(job.deps.iter().chain(discovered).map(String::as_str))
+    }
+
+    // Make-style out-of-date check. The job is considered stale when it's phony,
+    // when any of its outputs doesn't exist, when its commands have changed since it was built,
+    // when one of its dep jobs has been executed, or when any of its deps, including
+    // the ones discovered from its depfile, is newer than the target.
+    fn is_stale(&self, id: NodeId) -> bool {
+        let node = &self.rake.graph.nodes[id];
+        if node.kind == NodeKind::File { return false }
+
+        let target = node.name.as_str();
+        if self.always_make || self.rake.phony.contains(target) { return true }
+
+        let NodeKind::Job(idx) = node.kind else { return false };
+        let job = &self.rake.jobs[idx];
+        let Some(target_mtime) = Self::outputs_mtime(job) else { return true };
+
+        if self.db.changed(target, job.cmd_hash()) { return true }
+
+        // In the hash mode only the contents of the inputs matter, once the target is built in it
+        let content = self.db.get(target).and_then(|entry| entry.content);
+        if let Some((inputs, _)) = content.filter(|_| self.rake.is_hashed(target)) {
+            return inputs != Self::hash_inputs(job, self.discovered(target))
+                || node.deps.iter().any(|dep| {
+                    self.rake.phony.contains(&self.rake.graph.nodes[*dep].name)
+                    && matches!(self.states[*dep], State::Done(true))
+                })
+        }
+
+        node.deps.iter().any(|dep| {
+            matches!(self.states[*dep], State::Done(true))
+            || matches!(Self::mtime(&self.rake.graph.nodes[*dep].name), Some(dep_mtime) if dep_mtime > target_mtime)
+        }) || self.discovered(target).iter().any(|dep| {
+            // Discovered dep that is gone, like a removed header, needs a rebuild to find out
+            Self::mtime(dep).is_none_or(|dep_mtime| dep_mtime > target_mtime)
+        })
+    }
+
+    // Deps listed in the depfile of the job, except for the target and its explicit deps.
+    // Deps from the previous build are kept, if there is no depfile to read.
+    fn read_depfile(&self, job: &RJob) -> Vec::<String> {
+        let Some(file_str) = job.depfile.as_ref().and_then(|path| fs::read_to_string(path).ok()) else {
+            return self.discovered(&job.target).to_vec()
+        };
+
+        parse_depfile(&file_str).into_iter()
+            .filter(|dep| !job.outputs.contains(dep) && !job.deps.contains(dep))
+            .collect()
+    }
+
+    // Remembers the commands the target is up to date with, if the target was actually built
+    fn record(&mut self, id: NodeId) {
+        if !matches!(self.mode, Mode::Execute | Mode::Touch) { return }
+
+        let node = &self.rake.graph.nodes[id];
+        let NodeKind::Job(idx) = node.kind else { return };
+        let job = &self.rake.jobs[idx];
+        if self.rake.phony.contains(&node.name) || !job.outputs.iter().all(Rob::path_exists) { return }
+
+        let deps = self.read_depfile(job);
+        let content = self.rake.is_hashed(&node.name).then(|| {
+            (Self::hash_inputs(job, &deps), Self::hash_outputs(job).unwrap_or_default())
+        });
+        self.db.record(&node.name, Entry { cmd: job.cmd_hash(), content, deps });
+    }
+
+    // Whether the content of the executed target differs from the recorded one,
+    // targets that are not built in the hash mode are always considered changed.
+    fn output_changed(&self, id: NodeId) -> bool {
+        let node = &self.rake.graph.nodes[id];
+        let NodeKind::Job(idx) = node.kind else { return true };
+        if self.mode != Mode::Execute || !self.rake.is_hashed(&node.name) { return true }
+
+        match self.db.get(&node.name).and_then(|entry| entry.content) {
+            Some((_, output)) => Self::hash_outputs(&self.rake.jobs[idx]) != Some(output),
+            None => true
+        }
+    }
+
+    // Sets modification times of the outputs back to the ones they had before the job was executed
+    fn restore_mtimes(job: &RJob, mtimes: &[Option::<SystemTime>]) {
+        for (output, mtime) in job.outputs.iter().zip(mtimes) {
+            let Some(mtime) = mtime else { continue };
+            if let Err(err) = OpenOptions::new().write(true).open(output).and_then(|file| file.set_modified(*mtime)) {
+                log!(WARN, "Failed to restore modification time of `{output}`: {err}");
+            }
+        }
+    }
+
+    fn finish(&mut self, id: NodeId, state: State) {
+        self.states[id] = state;
+        if matches!(state, State::Failed) { return }
+
+        for dependent in self.rake.graph.nodes[id].dependents.iter() {
+            self.pending[*dependent] -= 1;
+            if self.pending[*dependent] == 0 && self.states[*dependent] == State::Waiting {
+                self.ready.push_back(*dependent);
+            }
+        }
+    }
+
+    // Returns whether each of the goals has been executed.
+    pub fn run(mut self) -> RResult::<Vec::<bool>> {
+        let env = self.rake.exported_env()?;
+
+        let mut error = None;
+        let (tx, rx) = mpsc::channel();
+
+        thread::scope(|s| {
+            let mut running = 0;
+            loop {
+                while running < self.jobs_n && error.is_none() {
+                    let Some(id) = self.ready.pop_front() else { break };
+                    let NodeKind::Job(idx) = self.rake.graph.nodes[id].kind else {
+                        self.finish(id, State::Done(false));
+                        continue
+                    };
+
+                    if !self.is_stale(id) {
+                        self.record(id);
+                        self.finish(id, State::Done(false));
+                        continue
+                    }
+
+                    let job = &self.rake.jobs[idx];
+                    let (tx, env, mode) = (tx.clone(), &env, self.mode);
+                    let touch = mode == Mode::Touch && !self.rake.phony.contains(&job.target);
+                    let mtimes = job.outputs.iter().map(|output| Self::mtime(output)).collect::<Vec::<_>>();
+                    self.states[id] = State::Running;
+                    s.spawn(move || {
+                        // The result is sent even if the job panics, e.g. when `log!` hits a closed stdout,
+                        // otherwise the main thread would wait for it forever.
+                        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                            job.execute(env, mode).and_then(|()| if touch { job.touch() } else { Ok(()) })
+                        })).unwrap_or_else(|_| Err(RakeError::FailedToExecute(job.info.to_owned(), "job panicked".to_owned())));
+                        _ = tx.send((id, result, mtimes));
+                    });
+                    running += 1;
+                }
+
+                if running == 0 { break }
+
+                let (id, result, mtimes) = rx.recv().unwrap();
+                running -= 1;
+                match result {
+                    Ok(()) => {
+                        let state = if self.output_changed(id) {
+                            State::Done(true)
+                        } else {
+                            if let NodeKind::Job(idx) = self.rake.graph.nodes[id].kind {
+                                Self::restore_mtimes(&self.rake.jobs[idx], &mtimes);
+                            }
+                            State::Unchanged
+                        };
+                        self.record(id);
+                        self.finish(id, state);
+                    }
+                    Err(err) => {
+                        self.finish(id, State::Failed);
+                        if self.keepgoing {
+                            log!(ERROR, "{err}");
+                        } else if error.is_none() {
+                            error = Some(err);
+                        }
+                    }
+                }
+            }
+        });
+
+        // Jobs that succeeded are recorded even if others failed
+        if let Err(err) = self.db.save() {
+            log!(WARN, "Failed to save `{db}`: {err}", db = Db::FILE_NAME);
+        }
+
+        if let Some(err) = error {
+            return Err(err)
+        }
+
+        let mut not_remade = None;
+        let executed = self.goals.iter().map(|goal| match self.states[*goal] {
+            State::Done(executed) => executed,
+            State::Unchanged => true,
+            _ => {
+                let target = &self.rake.graph.nodes[*goal].name;
+                if not_remade.is_none() {
+                    not_remade = Some(target.to_owned());
+                } else {
+                    log!(ERROR, "{err}", err = RakeError::NotRemade(target.to_owned()));
+                }
+                false
+            }
+        }).collect();
+
+        match not_remade {
+            Some(target) => Err(RakeError::NotRemade(target)),
+            None => Ok(executed)
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt::{Display, Formatter};
+
 // Allow people to use both Makefiles and Rakefiles
 // special symbols.
 pub enum SSymbol {
@@ -10,10 +12,19 @@ pub enum SSymbol {
     MakeDeps,
     RakeDeps,
 
+    // Stem of the matched pattern rule
+    Stem,
+
     MakePhony,
     RakePhony,
 
-    MakeSilent
+    MakeSilent,
+
+    // Targets to decide staleness of by content hashes, all of them if there are no deps
+    RakeHash,
+
+    // Targets, whose recipes write depfiles at: `$t.d`, all of them if there are no deps
+    RakeDepfile
 }
 
 impl TryFrom::<&String> for SSymbol {
@@ -22,34 +33,41 @@ impl TryFrom::<&String> for SSymbol {
     fn try_from(val: &String) -> Result<Self, Self::Error> {
         use SSymbol::*;
         match val.as_str() {
-            "$@"      => Ok(MakeTarget),
-            "$t"      => Ok(RakeTarget),
-            "$d"      => Ok(MakeDep),
-            "$<"      => Ok(RakeDep),
-            "$ds"     => Ok(MakeDeps),
-            "$^"      => Ok(RakeDeps),
-            ".PHONY"  => Ok(MakePhony),
-            ".ALWAYS" => Ok(RakePhony),
-            ".SILENT" => Ok(MakeSilent),
-            _         => Err(())
+            "$@"         => Ok(MakeTarget),
+            "$t"         => Ok(RakeTarget),
+            "$d"         => Ok(MakeDep),
+            "$<"         => Ok(RakeDep),
+            "$ds"        => Ok(MakeDeps),
+            "$^"         => Ok(RakeDeps),
+            "$*"         => Ok(Stem),
+            ".PHONY"     => Ok(MakePhony),
+            ".ALWAYS"    => Ok(RakePhony),
+            ".SILENT"    => Ok(MakeSilent),
+            ".RAKE_HASH" => Ok(RakeHash),
+            ".DEPFILE"   => Ok(RakeDepfile),
+            _            => Err(())
         }
     }
 }
 
-impl ToString for SSymbol {
-    fn to_string(&self) -> String {
+impl Display for SSymbol {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
         use SSymbol::*;
-        match self {
-            MakeTarget => "$@",
-            RakeTarget => "$t",
-            MakeDep    => "$d",
-            RakeDep    => "$<",
-            MakeDeps   => "$ds",
-            RakeDeps   => "$^",
-            MakePhony  => ".PHONY",
-            RakePhony  => ".ALWAYS",
-            MakeSilent => ".SILENT"
-        }.to_owned()
+        let s = match self {
+            MakeTarget  => "$@",
+            RakeTarget  => "$t",
+            MakeDep     => "$d",
+            RakeDep     => "$<",
+            MakeDeps    => "$ds",
+            RakeDeps    => "$^",
+            Stem        => "$*",
+            MakePhony   => ".PHONY",
+            RakePhony   => ".ALWAYS",
+            MakeSilent  => ".SILENT",
+            RakeHash    => ".RAKE_HASH",
+            RakeDepfile => ".DEPFILE"
+        };
+        write!(f, "{s}")
     }
 }
 
@@ -62,12 +80,12 @@ macro_rules! sreplace {
 
 #[macro_export]
 macro_rules! parse_special_job_by_target {
-    ($self: ident, $tar: expr, $deps: expr, $cmd: expr, $field: tt, $val: expr, $($ss: expr), *) => {{
+    ($self: ident, $tar: expr, $deps: expr, $field: tt, $val: expr, $($ss: expr), *) => {{
         let check = [$($ss), *].iter().any(|x| x.to_string().eq($tar));
         if check {
             for tar_ in $deps.iter() {
                 if let Some(job) = $self.find_job_by_target_mut(tar_) {
-                    job.0.$field($val);
+                    job.cfg.$field($val);
                 }
             }
         } check
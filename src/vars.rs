@@ -0,0 +1,80 @@
+use crate::Info;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flavor {
+    /// Value is expanded once, at the definition: `:=`
+    Simple,
+
+    /// Value is expanded every time the variable is used: `=`
+    Recursive
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Origin {
+    File,
+
+    /// Variables that are not defined in the Rakefile fall back to the process environment
+    Environment,
+
+    /// Passed like: `rake cc=clang`, takes precedence over assignments in the Rakefile
+    CommandLine
+}
+
+#[derive(Debug, Clone)]
+pub struct Var {
+    pub value: String,
+    pub flavor: Flavor,
+    pub origin: Origin
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AssignOp {
+    /// `=`
+    Recursive,
+
+    /// `:=` or `::=`
+    Simple,
+
+    /// `?=`, assign only if the variable is not defined yet
+    Conditional,
+
+    /// `+=`
+    Append
+}
+
+impl AssignOp {
+    // Returns the operator with its byte range in the line,
+    // or `None` if the line is not a variable declaration.
+    pub fn find(line: &str) -> Option::<(AssignOp, usize, usize)> {
+        use AssignOp::*;
+
+        let eq_idx = line.find('=')?;
+        let (op, start) = match line[..eq_idx].chars().last() {
+            Some(':') if line[..eq_idx].ends_with("::") => (Simple, eq_idx - 2),
+            Some(':') => (Simple, eq_idx - 1),
+            Some('?') => (Conditional, eq_idx - 1),
+            Some('+') => (Append, eq_idx - 1),
+            _         => (Recursive, eq_idx)
+        };
+
+        // `foo: bar=baz` is a job
+        if line[..start].contains(':') { return None }
+
+        Some((op, start, eq_idx + 1))
+    }
+}
+
+// State of a single expansion
+#[derive(Default)]
+pub struct Scope {
+    // Stack of the recursive variables being expanded right now, to catch
+    // variables that reference themselves, like: `a = $(b)`, `b = $(a)`.
+    pub expanding: Vec::<String>,
+
+    // Variables bound by `$(foreach ...)`, innermost last
+    pub locals: Vec::<(String, String)>,
+
+    // Location and source line to point errors at, if the expansion doesn't come from the line
+    // being parsed right now, like the values of the exported variables, that are expanded once parsing is done.
+    pub src: Option::<(Info, String)>
+}